@@ -1,4 +1,4 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
 
 fn build_cli() -> Command {
     Command::new("L2 Order Book CLI")
@@ -9,6 +9,7 @@ fn build_cli() -> Command {
                 .short('d')
                 .long("depth_limit")
                 .value_name("DEPTH_LIMIT")
+                .value_parser(clap::value_parser!(usize))
                 .help("Specifies the depth limit for the order book")
                 .required(false),
         )
@@ -17,7 +18,7 @@ fn build_cli() -> Command {
                 .short('i')
                 .long("instrument")
                 .value_name("INSTRUMENT")
-                .help("Specifies the trading instrument")
+                .help("Specifies the trading instruments, comma separated, e.g. BTC-USD,ETH-USD")
                 .required(false),
         )
         .arg(
@@ -28,6 +29,80 @@ fn build_cli() -> Command {
                 .help("Specifies the provider name")
                 .required(false),
         )
+        .arg(
+            Arg::new("feed")
+                .short('f')
+                .long("feed")
+                .value_name("FEED")
+                .help("Specifies the order book feed: snapshot or incremental")
+                .required(false),
+        )
+        .arg(
+            Arg::new("trades")
+                .short('t')
+                .long("trades")
+                .action(ArgAction::SetTrue)
+                .help("Streams the public trades of the instrument next to the book"),
+        )
+        .arg(
+            Arg::new("record")
+                .short('r')
+                .long("record")
+                .value_name("DIRECTORY")
+                .help("Records the raw provider frames to the directory")
+                .required(false),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .value_name("PATH")
+                .help("Replays the recorded frames of the file or directory")
+                .required(false),
+        )
+        .arg(
+            Arg::new("speed")
+                .long("speed")
+                .value_name("SPEED")
+                .help("Specifies the replay speed: max, realtime or a factor such as 10x")
+                .required(false),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FORMAT")
+                .help("Specifies the output: ui, or json and csv streamed to stdout")
+                .required(false),
+        )
+        .arg(
+            Arg::new("levels")
+                .long("levels")
+                .value_name("LEVELS")
+                .value_parser(clap::value_parser!(usize))
+                .help("Specifies the levels per side of the streamed output")
+                .required(false),
+        )
+        .arg(
+            Arg::new("ws")
+                .long("ws")
+                .value_name("ADDRESS")
+                .help("Serves the book to WebSocket clients on the address, e.g. 127.0.0.1:9001")
+                .required(false),
+        )
+        .arg(
+            Arg::new("http")
+                .long("http")
+                .value_name("ADDRESS")
+                .help("Serves the HTTP API on the address, e.g. 127.0.0.1:8080")
+                .required(false),
+        )
+        .arg(
+            Arg::new("metrics")
+                .long("metrics")
+                .value_name("ADDRESS")
+                .help("Serves the Prometheus metrics on the address, e.g. 127.0.0.1:9100")
+                .required(false),
+        )
 }
 
 pub fn get_cli_args() -> clap::ArgMatches {
@@ -46,6 +121,27 @@ mod tests {
         assert_eq!(matches.get_one::<String>("instrument").unwrap(), "BTC-USD");
     }
 
+    #[test]
+    fn test_output_arguments() {
+        let matches = build_cli()
+            .try_get_matches_from(vec!["test", "-o", "csv", "--levels", "3"])
+            .unwrap();
+        assert_eq!(matches.get_one::<String>("output").unwrap(), "csv");
+        assert_eq!(matches.get_one::<usize>("levels"), Some(&3));
+    }
+
+    #[test]
+    fn test_depth_limit_argument() {
+        let matches = build_cli()
+            .try_get_matches_from(vec!["test", "-d", "20"])
+            .unwrap();
+        assert_eq!(matches.get_one::<usize>("depth_limit"), Some(&20));
+        assert!(!matches.get_flag("trades"));
+        assert!(build_cli()
+            .try_get_matches_from(vec!["test", "--depth_limit", "deep"])
+            .is_err());
+    }
+
     #[test]
     fn test_short_instrument_argument() {
         let cmd = build_cli().try_get_matches_from(vec!["test", "-i", "ETH-USD"]);
@@ -1,3 +1,4 @@
+pub mod output;
 pub mod ui;
 
 use std::{env, io};
@@ -75,10 +76,10 @@ pub fn listen_user_input() -> mpsc::Receiver<()> {
         let mut event_stream = EventStream::new();
         loop {
             match event_stream.next().await {
-                Some(Ok(Event::Key(key))) => {
-                    if key.code == KeyCode::Esc || key.code == KeyCode::Char('q') {
-                        shutdown_tx.send(()).await.unwrap();
-                    }
+                Some(Ok(Event::Key(key)))
+                    if key.code == KeyCode::Esc || key.code == KeyCode::Char('q') =>
+                {
+                    shutdown_tx.send(()).await.unwrap();
                 }
                 Some(Err(e)) => {
                     // Handle error
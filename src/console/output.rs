@@ -0,0 +1,180 @@
+use std::{
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use serde::Serialize;
+use tokio::sync::mpsc::{self, Sender};
+
+use crate::core::{
+    fixed_point::Price,
+    order_book::{BookSnapshot, Level},
+    SharedOrderBook,
+};
+use crate::utils::config::OutputFormat;
+
+/// Book update as streamed in the JSON lines output.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BookRecord<'a> {
+    /// Time of the update, in microseconds since the Unix epoch.
+    pub timestamp_us: u64,
+    pub instrument: &'a str,
+    pub best_bid: Option<Price>,
+    pub best_ask: Option<Price>,
+    /// Top levels, best first.
+    pub bids: &'a [Level],
+    pub asks: &'a [Level],
+}
+
+impl<'a> BookRecord<'a> {
+    pub fn new(timestamp_us: u64, instrument: &'a str, snapshot: &'a BookSnapshot) -> Self {
+        BookRecord {
+            timestamp_us,
+            instrument,
+            best_bid: snapshot.best_bid,
+            best_ask: snapshot.best_ask,
+            bids: &snapshot.bids,
+            asks: &snapshot.asks,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// CSV row with `levels` price and quantity columns per side, missing levels left empty.
+    pub fn to_csv(&self, levels: usize) -> String {
+        let optional = |price: Option<Price>| price.map(|p| p.to_string()).unwrap_or_default();
+        let mut columns = vec![
+            self.timestamp_us.to_string(),
+            self.instrument.to_string(),
+            optional(self.best_bid),
+            optional(self.best_ask),
+        ];
+        for side in [self.bids, self.asks] {
+            for i in 0..levels {
+                match side.get(i) {
+                    Some(level) => {
+                        columns.push(level.price.to_string());
+                        columns.push(level.quantity.to_string());
+                    }
+                    None => columns.extend([String::new(), String::new()]),
+                }
+            }
+        }
+        columns.join(",")
+    }
+}
+
+/// CSV header matching `BookRecord::to_csv`.
+pub fn csv_header(levels: usize) -> String {
+    let mut columns = vec![
+        "timestamp_us".to_string(),
+        "instrument".to_string(),
+        "best_bid".to_string(),
+        "best_ask".to_string(),
+    ];
+    for side in ["bid", "ask"] {
+        for i in 1..=levels {
+            columns.push(format!("{}_price_{}", side, i));
+            columns.push(format!("{}_qty_{}", side, i));
+        }
+    }
+    columns.join(",")
+}
+
+/// Stream every change of the book to stdout as JSON lines or CSV, until stopped.
+///
+/// Changes are conflated like any other subscription, a slow reader sees the latest state.
+pub fn setup_stream_output(
+    order_book: SharedOrderBook,
+    format: OutputFormat,
+    instrument: String,
+    levels: usize,
+) -> Sender<()> {
+    let (stop_tx, mut stop_rx) = mpsc::channel(1);
+
+    tokio::task::spawn(async move {
+        let mut subscription = order_book.subscribe(Some(levels));
+        if format == OutputFormat::Csv && write_line(&csv_header(levels)).is_err() {
+            return;
+        }
+
+        loop {
+            let snapshot = tokio::select! {
+                snapshot = subscription.next() => snapshot,
+                _ = stop_rx.recv() => return,
+            };
+            let record = BookRecord::new(now_us(), &instrument, &snapshot);
+            let line = match format {
+                OutputFormat::Csv => record.to_csv(levels),
+                _ => record.to_json(),
+            };
+            // e.g. the reading end of the pipe is closed
+            if let Err(e) = write_line(&line) {
+                warn!("Stopped streaming the book: {}", e);
+                return;
+            }
+        }
+    });
+
+    stop_tx
+}
+
+fn write_line(line: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", line)?;
+    stdout.flush()
+}
+
+fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixed_point::Quantity;
+
+    fn snapshot() -> BookSnapshot {
+        let level = |price: f64, quantity: f64| Level {
+            price: Price::from_f64(price),
+            quantity: Quantity::from_f64(quantity),
+        };
+        BookSnapshot {
+            bids: vec![level(100.5, 1.0), level(100.0, 2.5)],
+            asks: vec![level(101.0, 3.0)],
+            best_bid: Some(Price::from_f64(100.5)),
+            best_ask: Some(Price::from_f64(101.0)),
+            sequence: None,
+        }
+    }
+
+    #[test]
+    fn test_json_record() {
+        let snapshot = snapshot();
+        let record = BookRecord::new(1, "BTC-USD", &snapshot);
+        assert_eq!(
+            record.to_json(),
+            r#"{"timestamp_us":1,"instrument":"BTC-USD","best_bid":"100.5","best_ask":"101","bids":[{"price":"100.5","quantity":"1"},{"price":"100","quantity":"2.5"}],"asks":[{"price":"101","quantity":"3"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_csv_record() {
+        let snapshot = snapshot();
+        let record = BookRecord::new(1, "BTC-USD", &snapshot);
+        assert_eq!(
+            csv_header(2),
+            "timestamp_us,instrument,best_bid,best_ask,bid_price_1,bid_qty_1,bid_price_2,bid_qty_2,ask_price_1,ask_qty_1,ask_price_2,ask_qty_2"
+        );
+        assert_eq!(
+            record.to_csv(2),
+            "1,BTC-USD,100.5,101,100.5,1,100,2.5,101,3,,"
+        );
+    }
+}
@@ -1,14 +1,44 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use ratatui::backend::Backend;
-use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::layout::{Alignment, Constraint, Direction, Layout};
 use ratatui::style::{Color, Style, Stylize};
-use ratatui::widgets::{Block, Borders, Cell, Row, Table};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Axis, Block, Borders, Cell, Chart, Dataset, GraphType, Paragraph, Row, Table,
+};
 use ratatui::{Frame, Terminal};
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
 
-use tokio::time;
+use crate::core::{
+    analytics,
+    fixed_point::{Price, Quantity},
+    messages::{Side, Trade},
+    order_book::{BookSnapshot, Level},
+    trades::{RollingVolume, Volume},
+    SharedOrderBook,
+};
 
-use crate::core::SharedOrderBook;
+/// Windows of the rolling volume panel, the longest is the horizon of the totals.
+const VOLUME_WINDOWS: [(&str, Duration); 3] = [
+    ("1m", Duration::from_secs(60)),
+    ("5m", Duration::from_secs(5 * 60)),
+    ("1h", Duration::from_secs(60 * 60)),
+];
+
+/// Cells of the size bar of the largest level in the ladder.
+const BAR_WIDTH: usize = 12;
+/// Share of the price padded on both sides of a depth chart with a single price.
+const SINGLE_PRICE_PADDING: f64 = 0.001;
+/// Levels per side the UI subscribes to, the ladder and the depth chart show no more.
+const BOOK_LEVELS: usize = 50;
+/// Basis points around the mid of the depth chart, far levels would squash the spread.
+const CHART_WINDOW_BPS: f64 = 100.0;
+const BPS: f64 = 10_000.0;
+
+/// Trades kept on the tape, more than fit any terminal.
+const TAPE_TRADES: usize = 200;
 
 pub struct Ui {
     order_book: SharedOrderBook,
@@ -23,21 +53,76 @@ impl Ui {
         &self,
         terminal: &mut Terminal<B>,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let trades = self.order_book.trades();
+        let mut subscription = self.order_book.subscribe(Some(BOOK_LEVELS));
+        let mut trade_stream = trades.subscribe();
+        let mut snapshot = self.order_book.snapshot(Some(BOOK_LEVELS)).await;
+
+        // totals start from the buffered trades, then follow the stream
+        let horizon = VOLUME_WINDOWS[VOLUME_WINDOWS.len() - 1].1;
+        let mut rolling_volume = RollingVolume::new(horizon);
+        let from = now_us().saturating_sub(horizon.as_micros() as u64);
+        let buffered = trades.since(from);
+        for trade in &buffered {
+            rolling_volume.record(trade);
+        }
+        // the stream is subscribed first, it repeats the latest buffered trades
+        let backfill = Backfill::new(&buffered);
+        let mut trades_open = true;
+        // the windows roll even when nothing trades
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+
         loop {
-            let best_bid = self.order_book.get_best_bid().await.unwrap_or_default();
-            let best_ask = self.order_book.get_best_ask().await.unwrap_or_default();
-            let bids = self.order_book.get_bids().await;
-            let asks = self.order_book.get_asks().await;
+            let now = now_us();
+            let volumes =
+                VOLUME_WINDOWS.map(|(name, window)| (name, rolling_volume.volume(window, now)));
+            let tape = trades.recent(TAPE_TRADES);
 
             terminal.draw(|f| {
-                self.draw_ui(f, best_bid, best_ask, bids, asks);
+                self.draw_ui(f, &snapshot, &volumes, &tape);
             })?;
 
-            time::sleep(Duration::from_secs(1)).await; // update UI every second
+            // redraw on every change of the book, both sides from the same moment
+            tokio::select! {
+                next = subscription.next() => snapshot = next,
+                trade = trade_stream.recv(), if trades_open => {
+                    // count the whole burst before drawing once, trades skipped
+                    // by a lagging stream are missing from the totals
+                    let mut next = trade;
+                    loop {
+                        match next {
+                            Ok(trade) if backfill.covers(&trade) => {}
+                            Ok(trade) => rolling_volume.record(&trade),
+                            Err(RecvError::Lagged(_)) => {}
+                            Err(RecvError::Closed) => {
+                                trades_open = false;
+                                break;
+                            }
+                        }
+                        next = match trade_stream.try_recv() {
+                            Ok(trade) => Ok(trade),
+                            Err(TryRecvError::Lagged(skipped)) => Err(RecvError::Lagged(skipped)),
+                            Err(_) => break,
+                        };
+                    }
+                }
+                // only redraws the rolling windows, book changes are never lost by
+                // the other branches as the subscription is cancel safe
+                _ = tick.tick() => {}
+            }
         }
     }
 
-    fn draw_ui(&self, f: &mut Frame, best_bid: f64, best_ask: f64, bids: Vec<f64>, asks: Vec<f64>) {
+    fn draw_ui(
+        &self,
+        f: &mut Frame,
+        snapshot: &BookSnapshot,
+        volumes: &[(&str, Volume)],
+        tape: &[Trade],
+    ) {
+        let best_bid = snapshot.best_bid.unwrap_or_default();
+        let best_ask = snapshot.best_ask.unwrap_or_default();
+
         // Layout for the UI
         let chunks = Layout::default()
             .direction(Direction::Vertical)
@@ -46,11 +131,16 @@ impl Ui {
                 [
                     Constraint::Length(3),
                     Constraint::Min(1),
+                    Constraint::Percentage(35),
                     Constraint::Length(1),
                 ]
                 .as_ref(),
             )
             .split(f.size());
+        let middle = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(chunks[1]);
 
         // Best prices row
         let best_prices_row = Row::new(vec![
@@ -65,33 +155,204 @@ impl Ui {
         )
         .block(Block::default().borders(Borders::ALL).title("Best Prices"));
 
-        // Order book rows
-        let mut rows = vec![];
-        let len = bids.len().max(asks.len());
-        for i in 0..len {
-            let bid = bids.get(i).cloned().unwrap_or(0.0);
-            let ask = asks.get(i).cloned().unwrap_or(0.0);
-            rows.push(Row::new(vec![
-                Cell::from(format!("{}", ask)).style(Style::default().fg(Color::Blue)),
-                Cell::from(format!("{}", bid)).style(Style::default().fg(Color::Blue)),
-            ]));
-        }
+        // Ladder rows, asks above the spread and bids below, as many levels as fit
+        // between the borders, the header and the spread row
+        let levels = (middle[0].height.saturating_sub(4) / 2) as usize;
+        let (asks, bids) = ladder(snapshot, levels);
+        let largest = asks
+            .iter()
+            .chain(&bids)
+            .map(|rung| rung.quantity)
+            .max()
+            .unwrap_or_default();
+        let mut rows: Vec<Row> = asks
+            .iter()
+            .map(|rung| {
+                let style = Style::default().fg(Color::Red);
+                Row::new(vec![
+                    Cell::default(),
+                    Cell::default(),
+                    Cell::default(),
+                    Cell::default(),
+                    right(format!("{}", rung.price)).style(style.bold()),
+                    right(format!("{}", rung.quantity)),
+                    right(format!("{}", rung.cumulative)),
+                    Cell::from(bar(rung.quantity, largest, BAR_WIDTH)).style(style),
+                ])
+            })
+            .collect();
+        let spread =
+            analytics::spread(snapshot).map_or("-".to_string(), |spread| spread.to_string());
+        rows.push(
+            Row::new(vec![
+                Cell::default(),
+                Cell::default(),
+                Cell::default(),
+                right("Spread".to_string()),
+                right(spread),
+            ])
+            .style(Style::default().italic()),
+        );
+        rows.extend(bids.iter().map(|rung| {
+            let style = Style::default().fg(Color::Green);
+            Row::new(vec![
+                right(bar(rung.quantity, largest, BAR_WIDTH)).style(style),
+                right(format!("{}", rung.cumulative)),
+                right(format!("{}", rung.quantity)),
+                right(format!("{}", rung.price)).style(style.bold()),
+            ])
+        }));
 
-        // Order book table
+        // Ladder table, bid size | bid price | ask price | ask size with the cumulative
+        // sizes and bars proportional to the size of the level on the outside
         let order_book_table = Table::new(
             rows,
-            vec![Constraint::Percentage(50), Constraint::Percentage(50)],
+            vec![
+                Constraint::Length(BAR_WIDTH as u16),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Length(BAR_WIDTH as u16),
+            ],
         )
         .header(Row::new(vec![
-            Cell::from("Ask Price"),
-            Cell::from("Bid Price"),
+            Cell::default(),
+            right("Total".to_string()),
+            right("Size".to_string()),
+            right("Bid".to_string()),
+            right("Ask".to_string()),
+            right("Size".to_string()),
+            right("Total".to_string()),
+            Cell::default(),
         ]))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("All Order Book Prices"),
+        .block(Block::default().borders(Borders::ALL).title("Order Book"));
+
+        // Rolling volume rows
+        let volume_rows = volumes.iter().map(|(window, volume)| {
+            let ratio = volume
+                .buy_sell_ratio()
+                .map_or("-".to_string(), |ratio| format!("{:.2}", ratio));
+            Row::new(vec![
+                Cell::from(window.to_string()),
+                Cell::from(format!("{}", volume.buy)).style(Style::default().fg(Color::Green)),
+                Cell::from(format!("{}", volume.sell)).style(Style::default().fg(Color::Red)),
+                Cell::from(format!("{}", volume.total())),
+                Cell::from(ratio),
+            ])
+        });
+
+        // Rolling volume table
+        let volume_table = Table::new(
+            volume_rows,
+            vec![
+                Constraint::Length(6),
+                Constraint::Percentage(25),
+                Constraint::Percentage(25),
+                Constraint::Percentage(25),
+                Constraint::Min(9),
+            ],
         )
-        .widths([Constraint::Percentage(20), Constraint::Percentage(20)]);
+        .header(Row::new(vec![
+            Cell::from("Window"),
+            Cell::from("Buy"),
+            Cell::from("Sell"),
+            Cell::from("Total"),
+            Cell::from("Buy/Sell"),
+        ]))
+        .block(Block::default().borders(Borders::ALL).title("Volume"));
+
+        // Time and sales rows, newest on top and coloured by the aggressor
+        let tape_rows: Vec<Row> = tape
+            .iter()
+            .map(|trade| {
+                let (side, color) = match trade.aggressor {
+                    Side::Buy => ("Buy", Color::Green),
+                    Side::Sell => ("Sell", Color::Red),
+                };
+                Row::new(vec![
+                    Cell::from(format_time(trade.timestamp_us)),
+                    Cell::from(format!("{}", trade.price)),
+                    Cell::from(format!("{}", trade.quantity)),
+                    Cell::from(side),
+                ])
+                .style(Style::default().fg(color))
+            })
+            .collect();
+        // Time and sales table
+        let tape_table = Table::new(
+            tape_rows,
+            vec![
+                Constraint::Length(12),
+                Constraint::Percentage(35),
+                Constraint::Percentage(35),
+                Constraint::Min(4),
+            ],
+        )
+        .header(Row::new(vec![
+            Cell::from("Time"),
+            Cell::from("Price"),
+            Cell::from("Size"),
+            Cell::from("Side"),
+        ]))
+        .block(Block::default().borders(Borders::ALL).title("Time & Sales"));
+
+        // Hint in place of an empty tape, a table cell would cut it to the time column
+        let no_trades = Paragraph::new("No trades yet, enable with --trades")
+            .style(Style::default().italic())
+            .block(Block::default().borders(Borders::ALL).title("Time & Sales"));
+
+        // Depth chart, cumulative size of each side stepping out from the mid
+        let mid = analytics::mid(snapshot).map(|mid| mid.to_f64());
+        let bid_curve = depth_curve(chart_levels(&snapshot.bids, mid));
+        let ask_curve = depth_curve(chart_levels(&snapshot.asks, mid));
+        let (low, high) = price_bounds(mid, &bid_curve, &ask_curve);
+        let largest_total = bid_curve
+            .iter()
+            .chain(&ask_curve)
+            .map(|(_, size)| *size)
+            .fold(0.0, f64::max);
+        let size_top = if largest_total > 0.0 {
+            largest_total
+        } else {
+            1.0
+        };
+        let depth_chart = Chart::new(vec![
+            Dataset::default()
+                .name("Bids")
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Green))
+                .data(&bid_curve),
+            Dataset::default()
+                .name("Asks")
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Red))
+                .data(&ask_curve),
+        ])
+        .block(Block::default().borders(Borders::ALL).title("Depth"))
+        .x_axis(
+            Axis::default().title("Price").bounds([low, high]).labels(
+                [low, mid.unwrap_or((low + high) / 2.0), high]
+                    .iter()
+                    .map(|price| Span::from(Price::from_f64(*price).to_string()))
+                    .collect(),
+            ),
+        )
+        .y_axis(
+            Axis::default()
+                .title("Size")
+                .bounds([0.0, size_top])
+                .labels(
+                    [0.0, size_top / 2.0, size_top]
+                        .iter()
+                        .map(|size| Span::from(Quantity::from_f64(*size).to_string()))
+                        .collect(),
+                ),
+        );
 
         // Exit info row
         let exit_info_row = Row::new(vec![
@@ -104,7 +365,288 @@ impl Ui {
 
         // Render the tables
         f.render_widget(best_prices_table, chunks[0]);
-        f.render_widget(order_book_table, chunks[1]);
-        f.render_widget(exit_info_table, chunks[2]);
+        let trades_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(volumes.len() as u16 + 3),
+                    Constraint::Min(1),
+                ]
+                .as_ref(),
+            )
+            .split(middle[1]);
+        f.render_widget(order_book_table, middle[0]);
+        f.render_widget(volume_table, trades_chunks[0]);
+        if tape.is_empty() {
+            f.render_widget(no_trades, trades_chunks[1]);
+        } else {
+            f.render_widget(tape_table, trades_chunks[1]);
+        }
+        f.render_widget(depth_chart, chunks[2]);
+        f.render_widget(exit_info_table, chunks[3]);
+    }
+}
+
+/// Latest trades counted from the buffer, to skip them when the stream repeats them.
+struct Backfill {
+    timestamp_us: u64,
+    /// Trades of the latest timestamp, later trades may share it.
+    trade_ids: Vec<String>,
+}
+
+impl Backfill {
+    fn new(buffered: &[Trade]) -> Self {
+        let timestamp_us = buffered.last().map_or(0, |trade| trade.timestamp_us);
+        let trade_ids = buffered
+            .iter()
+            .rev()
+            .take_while(|trade| trade.timestamp_us == timestamp_us)
+            .map(|trade| trade.trade_id.clone())
+            .collect();
+        Backfill {
+            timestamp_us,
+            trade_ids,
+        }
+    }
+
+    fn covers(&self, trade: &Trade) -> bool {
+        trade.timestamp_us < self.timestamp_us
+            || (trade.timestamp_us == self.timestamp_us && self.trade_ids.contains(&trade.trade_id))
+    }
+}
+
+/// Level of one side of the ladder.
+#[derive(Debug, PartialEq)]
+struct Rung {
+    price: Price,
+    quantity: Quantity,
+    /// Size from the best price up to and including the level.
+    cumulative: Quantity,
+}
+
+/// Up to `levels` asks from the farthest down to the best, and bids from the best down.
+fn ladder(snapshot: &BookSnapshot, levels: usize) -> (Vec<Rung>, Vec<Rung>) {
+    let rungs = |side: &[Level]| -> Vec<Rung> {
+        let mut cumulative = Quantity::default();
+        side.iter()
+            .take(levels)
+            .map(|level| {
+                cumulative += level.quantity;
+                Rung {
+                    price: level.price,
+                    quantity: level.quantity,
+                    cumulative,
+                }
+            })
+            .collect()
+    };
+    let mut asks = rungs(&snapshot.asks);
+    asks.reverse();
+    (asks, rungs(&snapshot.bids))
+}
+
+/// Bar of `quantity` relative to `largest`, `width` cells for the largest and at least
+/// one cell for any size.
+fn bar(quantity: Quantity, largest: Quantity, width: usize) -> String {
+    if quantity.is_zero() || largest.is_zero() {
+        return String::new();
+    }
+    let cells = (quantity.to_f64() / largest.to_f64() * width as f64).round() as usize;
+    "█".repeat(cells.clamp(1, width))
+}
+
+/// Points of the stepped cumulative size curve of one side, from the best level outwards.
+///
+/// The curve rises at every price by the size of the level and stays flat up to the next.
+fn depth_curve(levels: &[Level]) -> Vec<(f64, f64)> {
+    let mut cumulative = Quantity::default();
+    let mut points = Vec::with_capacity(levels.len() * 2);
+    for level in levels {
+        let price = level.price.to_f64();
+        points.push((price, cumulative.to_f64()));
+        cumulative += level.quantity;
+        points.push((price, cumulative.to_f64()));
+    }
+    points
+}
+
+/// Half width of the depth chart around the mid.
+fn chart_window(mid: f64) -> f64 {
+    mid.abs() * CHART_WINDOW_BPS / BPS
+}
+
+/// Best levels of one side within the chart window around the mid, all without a mid.
+fn chart_levels(levels: &[Level], mid: Option<f64>) -> &[Level] {
+    let Some(mid) = mid else {
+        return levels;
+    };
+    let window = chart_window(mid);
+    let end = levels
+        .iter()
+        .position(|level| (level.price.to_f64() - mid).abs() > window)
+        .unwrap_or(levels.len());
+    &levels[..end]
+}
+
+/// Price range of the depth chart, centred on the mid so both sides share the scale.
+///
+/// The range stays within the chart window, an outlier level does not stretch it.
+/// A single price, e.g. one level without a mid, is padded so the range is never empty.
+fn price_bounds(mid: Option<f64>, bids: &[(f64, f64)], asks: &[(f64, f64)]) -> (f64, f64) {
+    let prices = || bids.iter().chain(asks).map(|(price, _)| *price);
+    let low = prices().fold(f64::INFINITY, f64::min);
+    let high = prices().fold(f64::NEG_INFINITY, f64::max);
+    let (low, high) = match mid {
+        Some(mid) => {
+            let half = (mid - low).max(high - mid).min(chart_window(mid));
+            (mid - half, mid + half)
+        }
+        None if low <= high => (low, high),
+        None => return (0.0, 1.0),
+    };
+    if low < high {
+        return (low, high);
+    }
+    let padding = match low.abs() * SINGLE_PRICE_PADDING {
+        padding if padding > 0.0 => padding,
+        _ => 0.5,
+    };
+    (low - padding, high + padding)
+}
+
+/// Right aligned cell, numbers of the ladder line up on the decimal side.
+fn right<'a>(text: String) -> Cell<'a> {
+    Cell::from(Line::from(text).alignment(Alignment::Right))
+}
+
+fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_micros() as u64)
+}
+
+/// Time of day of the exchange timestamp, `HH:MM:SS.mmm` in UTC.
+fn format_time(timestamp_us: u64) -> String {
+    let millis = timestamp_us / 1_000;
+    let seconds = millis / 1_000;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+        millis % 1_000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: f64, quantity: f64) -> Level {
+        Level {
+            price: Price::from_f64(price),
+            quantity: Quantity::from_f64(quantity),
+        }
+    }
+
+    #[test]
+    fn test_ladder() {
+        let snapshot = BookSnapshot {
+            bids: vec![level(99.0, 1.0), level(98.0, 2.0), level(97.0, 3.0)],
+            asks: vec![level(101.0, 0.5), level(102.0, 1.5)],
+            best_bid: Some(Price::from_f64(99.0)),
+            best_ask: Some(Price::from_f64(101.0)),
+            sequence: None,
+        };
+
+        let (asks, bids) = ladder(&snapshot, 2);
+        let prices = |rungs: &[Rung]| {
+            rungs
+                .iter()
+                .map(|rung| rung.price.to_f64())
+                .collect::<Vec<_>>()
+        };
+        // best ask and best bid meet at the spread
+        assert_eq!(prices(&asks), vec![102.0, 101.0]);
+        assert_eq!(prices(&bids), vec![99.0, 98.0]);
+        assert_eq!(asks[0].cumulative, Quantity::from_f64(2.0));
+        assert_eq!(bids[1].cumulative, Quantity::from_f64(3.0));
+    }
+
+    #[test]
+    fn test_depth_curve() {
+        let bids = [level(999.0, 1.0), level(998.0, 2.0)];
+        assert_eq!(
+            depth_curve(&bids),
+            vec![(999.0, 0.0), (999.0, 1.0), (998.0, 1.0), (998.0, 3.0)]
+        );
+        assert!(depth_curve(&[]).is_empty());
+
+        let asks = depth_curve(&[level(1001.0, 0.5), level(1004.0, 1.0)]);
+        // the wider side sets the range around the mid
+        assert_eq!(
+            price_bounds(Some(1000.0), &depth_curve(&bids), &asks),
+            (996.0, 1004.0)
+        );
+        assert_eq!(price_bounds(None, &[], &asks), (1001.0, 1004.0));
+        assert_eq!(price_bounds(None, &[], &[]), (0.0, 1.0));
+
+        // a single level without a mid, or a locked book, still spans a range
+        let single = depth_curve(&[level(100.0, 1.0)]);
+        assert_eq!(price_bounds(None, &single, &[]), (99.9, 100.1));
+        assert_eq!(price_bounds(Some(100.0), &single, &single), (99.9, 100.1));
+        assert_eq!(
+            price_bounds(None, &depth_curve(&[level(0.0, 1.0)]), &[]),
+            (-0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn test_chart_window_skips_outliers() {
+        let bids = [level(99.5, 1.0), level(99.0, 2.0), level(1.0, 100.0)];
+        let asks = [level(100.5, 1.0), level(1_000_000.0, 100.0)];
+
+        // the range stays within 100 bps of the mid
+        assert_eq!(
+            price_bounds(Some(100.0), &depth_curve(&bids), &depth_curve(&asks)),
+            (99.0, 101.0)
+        );
+        assert_eq!(chart_levels(&bids, Some(100.0)), &bids[..2]);
+        assert_eq!(chart_levels(&asks, Some(100.0)), &asks[..1]);
+        assert_eq!(chart_levels(&asks, None), &asks[..]);
+    }
+
+    #[test]
+    fn test_bar() {
+        let largest = Quantity::from_f64(4.0);
+        assert_eq!(bar(largest, largest, 8), "█".repeat(8));
+        assert_eq!(bar(Quantity::from_f64(1.0), largest, 8), "██");
+        assert_eq!(bar(Quantity::from_f64(0.01), largest, 8), "█");
+        assert_eq!(bar(Quantity::default(), largest, 8), "");
+    }
+
+    #[test]
+    fn test_backfill_covers_buffered_trades() {
+        let trade = |id: &str, timestamp_us: u64| Trade {
+            price: Price::from_f64(100.0),
+            quantity: Quantity::from_f64(1.0),
+            aggressor: Side::Buy,
+            trade_id: id.to_string(),
+            timestamp_us,
+        };
+        let backfill = Backfill::new(&[trade("1", 1_000), trade("2", 2_000), trade("3", 2_000)]);
+
+        assert!(backfill.covers(&trade("1", 1_000)));
+        assert!(backfill.covers(&trade("3", 2_000)));
+        // a later trade of the same time is counted
+        assert!(!backfill.covers(&trade("4", 2_000)));
+        assert!(!backfill.covers(&trade("5", 3_000)));
+        assert!(!Backfill::new(&[]).covers(&trade("1", 1_000)));
+    }
+
+    #[test]
+    fn test_format_time() {
+        // 2024-01-01T13:04:05.678Z
+        assert_eq!(format_time(1_704_114_245_678_901), "13:04:05.678");
     }
 }
@@ -0,0 +1,206 @@
+use super::fixed_point::{Price, Quantity};
+use super::messages::Side;
+use super::order_book::{BookSnapshot, Level};
+
+const BPS: f64 = 10_000.0;
+
+/// Result of sweeping a quantity through the levels of one side.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sweep {
+    /// Filled quantity, less than requested when the side is too thin.
+    pub filled: Quantity,
+    /// Volume weighted average price of the fill.
+    pub vwap: Price,
+    /// Price of the last level reached.
+    pub worst_price: Price,
+    /// Distance of the VWAP from the mid in bps, if the mid is known.
+    pub cost_bps: Option<f64>,
+}
+
+impl Sweep {
+    pub fn is_complete(&self, quantity: Quantity) -> bool {
+        self.filled >= quantity
+    }
+}
+
+fn levels(snapshot: &BookSnapshot, side: Side) -> &[Level] {
+    match side {
+        Side::Buy => &snapshot.bids,
+        Side::Sell => &snapshot.asks,
+    }
+}
+
+fn best(snapshot: &BookSnapshot) -> Option<(&Level, &Level)> {
+    Some((snapshot.bids.first()?, snapshot.asks.first()?))
+}
+
+/// Middle of the best bid and the best ask.
+pub fn mid(snapshot: &BookSnapshot) -> Option<Price> {
+    let (bid, ask) = best(snapshot)?;
+    Some(Price::from_units(
+        ((bid.price.units() as i128 + ask.price.units() as i128) / 2) as i64,
+    ))
+}
+
+/// Best ask minus best bid.
+pub fn spread(snapshot: &BookSnapshot) -> Option<Price> {
+    let (bid, ask) = best(snapshot)?;
+    Some(ask.price - bid.price)
+}
+
+/// Spread relative to the mid in basis points.
+pub fn spread_bps(snapshot: &BookSnapshot) -> Option<f64> {
+    let mid = mid(snapshot)?;
+    if mid.is_zero() {
+        return None;
+    }
+    Some(spread(snapshot)?.to_f64() / mid.to_f64() * BPS)
+}
+
+/// Mid weighted by the opposite top of book quantities, leans towards the thinner side.
+pub fn microprice(snapshot: &BookSnapshot) -> Option<Price> {
+    let (bid, ask) = best(snapshot)?;
+    let total = bid.quantity.units() as i128 + ask.quantity.units() as i128;
+    if total == 0 {
+        return mid(snapshot);
+    }
+    let weighted = bid.price.units() as i128 * ask.quantity.units() as i128
+        + ask.price.units() as i128 * bid.quantity.units() as i128;
+    Some(Price::from_units((weighted / total) as i64))
+}
+
+/// Volume imbalance of the best `n` levels in `[-1, 1]`, positive when bids dominate.
+pub fn imbalance(snapshot: &BookSnapshot, n: usize) -> Option<f64> {
+    let volume = |side| -> Quantity {
+        levels(snapshot, side)
+            .iter()
+            .take(n)
+            .map(|l| l.quantity)
+            .sum()
+    };
+    let (bids, asks) = (volume(Side::Buy).to_f64(), volume(Side::Sell).to_f64());
+    if bids + asks == 0.0 {
+        return None;
+    }
+    Some((bids - asks) / (bids + asks))
+}
+
+/// Cumulative quantity of the side priced within `bps` of the mid.
+pub fn depth_within_bps(snapshot: &BookSnapshot, side: Side, bps: f64) -> Option<Quantity> {
+    let mid = mid(snapshot)?;
+    let distance = Price::from_f64(mid.to_f64() * bps / BPS);
+    let within = |level: &&Level| match side {
+        Side::Buy => level.price >= mid - distance,
+        Side::Sell => level.price <= mid + distance,
+    };
+    Some(
+        levels(snapshot, side)
+            .iter()
+            .take_while(within)
+            .map(|level| level.quantity)
+            .sum(),
+    )
+}
+
+/// Sweep the quantity through the side best-first, `Side::Sell` is a buy lifting the asks
+/// and `Side::Buy` a sell hitting the bids. `None` when the side is empty.
+pub fn sweep(snapshot: &BookSnapshot, side: Side, quantity: Quantity) -> Option<Sweep> {
+    let mut remaining = quantity;
+    let mut filled = Quantity::ZERO;
+    let mut notional: i128 = 0;
+    let mut worst_price = None;
+
+    for level in levels(snapshot, side) {
+        if remaining <= Quantity::ZERO {
+            break;
+        }
+        let take = remaining.min(level.quantity);
+        notional += level.price.units() as i128 * take.units() as i128;
+        filled += take;
+        remaining -= take;
+        worst_price = Some(level.price);
+    }
+
+    let worst_price = worst_price?;
+    if filled.is_zero() {
+        return None;
+    }
+    let vwap = Price::from_units((notional / filled.units() as i128) as i64);
+    let cost_bps = mid(snapshot)
+        .filter(|mid| !mid.is_zero())
+        .map(|mid| (vwap - mid).to_f64().abs() / mid.to_f64() * BPS);
+
+    Some(Sweep {
+        filled,
+        vwap,
+        worst_price,
+        cost_bps,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: f64, quantity: f64) -> Level {
+        Level {
+            price: Price::from_f64(price),
+            quantity: Quantity::from_f64(quantity),
+        }
+    }
+
+    fn book() -> BookSnapshot {
+        BookSnapshot {
+            bids: vec![level(99.0, 3.0), level(98.0, 2.0), level(90.0, 10.0)],
+            asks: vec![level(101.0, 1.0), level(102.0, 4.0)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_top_of_book_metrics() {
+        let book = book();
+
+        assert_eq!(mid(&book), Some(Price::from_f64(100.0)));
+        assert_eq!(spread(&book), Some(Price::from_f64(2.0)));
+        assert_eq!(spread_bps(&book), Some(200.0));
+        // thin ask pulls the microprice up: (99 * 1 + 101 * 3) / 4
+        assert_eq!(microprice(&book), Some(Price::from_f64(100.5)));
+        assert_eq!(imbalance(&book, 1), Some(0.5));
+        assert_eq!(imbalance(&book, 2), Some(0.0));
+
+        assert!(mid(&BookSnapshot::default()).is_none());
+    }
+
+    #[test]
+    fn test_depth_within_bps() {
+        let book = book();
+
+        assert_eq!(
+            depth_within_bps(&book, Side::Buy, 200.0),
+            Some(Quantity::from_f64(5.0))
+        );
+        assert_eq!(
+            depth_within_bps(&book, Side::Sell, 100.0),
+            Some(Quantity::from_f64(1.0))
+        );
+    }
+
+    #[test]
+    fn test_sweep() {
+        let book = book();
+
+        let buy = sweep(&book, Side::Sell, Quantity::from_f64(3.0)).unwrap();
+        assert_eq!(buy.filled, Quantity::from_f64(3.0));
+        // (101 * 1 + 102 * 2) / 3
+        assert_eq!(buy.vwap.to_string(), "101.66666666");
+        assert_eq!(buy.worst_price, Price::from_f64(102.0));
+
+        let sell = sweep(&book, Side::Buy, Quantity::from_f64(100.0)).unwrap();
+        assert!(!sell.is_complete(Quantity::from_f64(100.0)));
+        assert_eq!(sell.filled, Quantity::from_f64(15.0));
+        assert_eq!(sell.worst_price, Price::from_f64(90.0));
+
+        assert!(sweep(&BookSnapshot::default(), Side::Buy, Quantity::from_f64(1.0)).is_none());
+    }
+}
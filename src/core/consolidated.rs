@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+
+use super::fixed_point::{Price, Quantity, DECIMALS};
+use super::messages::Side;
+use super::order_book::{BookSnapshot, Level};
+use super::SharedOrderBook;
+
+/// Unit of the quantities of a venue book.
+///
+/// The consolidated book adds quantities of different venues, so every venue quantity
+/// is converted to units of the base currency first.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum QuantityUnit {
+    /// Units of the base currency, e.g. BTC on Bitstamp BTC-USD.
+    #[default]
+    Base,
+    /// Linear contracts worth the given base quantity each.
+    Contracts(Quantity),
+    /// Units of the quote currency, e.g. USD of the inverse Deribit BTC-PERPETUAL.
+    Quote,
+}
+
+impl QuantityUnit {
+    /// Quantity at the price in units of the base currency, rounded to the nearest unit
+    /// of the fixed-point type. A non-zero quantity stays non-zero.
+    pub fn to_base(self, price: Price, quantity: Quantity) -> Quantity {
+        let units = match self {
+            QuantityUnit::Base => return quantity,
+            QuantityUnit::Contracts(size) => {
+                div_round(quantity.units() as i128 * size.units() as i128, SCALE)
+            }
+            QuantityUnit::Quote => {
+                div_round(quantity.units() as i128 * SCALE, price.units() as i128)
+            }
+        };
+        if units == 0 && !quantity.is_zero() {
+            return Quantity::from_units(1);
+        }
+        Quantity::from_units(units.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+}
+
+const SCALE: i128 = 10i128.pow(DECIMALS);
+
+fn div_round(numerator: i128, denominator: i128) -> i128 {
+    if denominator == 0 {
+        return 0;
+    }
+    (2 * numerator + denominator.signum() * numerator.signum() * denominator.abs())
+        / (2 * denominator)
+}
+
+/// Quantity a venue shows at a price level, in units of the base currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VenueQuantity {
+    pub venue: String,
+    pub quantity: Quantity,
+}
+
+/// Price level aggregated across venues, with the quantity of every venue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsolidatedLevel {
+    pub price: Price,
+    pub quantity: Quantity,
+    pub venues: Vec<VenueQuantity>,
+}
+
+impl ConsolidatedLevel {
+    /// Aggregated price and quantity without the attribution.
+    pub fn level(&self) -> Level {
+        Level {
+            price: self.price,
+            quantity: self.quantity,
+        }
+    }
+}
+
+/// National best bid and offer style top of the consolidated book.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Nbbo {
+    pub bid: Option<ConsolidatedLevel>,
+    pub ask: Option<ConsolidatedLevel>,
+}
+
+impl Nbbo {
+    /// Best bid of one venue is at or above the best ask of another one.
+    pub fn is_crossed(&self) -> bool {
+        match (&self.bid, &self.ask) {
+            (Some(bid), Some(ask)) => bid.price >= ask.price,
+            _ => false,
+        }
+    }
+}
+
+/*
+    Same layout as the order book, price levels in ascending order,
+    each level keeps the quantity per venue in units of the base currency.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ConsolidatedBook {
+    bids: BTreeMap<Price, BTreeMap<String, Quantity>>,
+    asks: BTreeMap<Price, BTreeMap<String, Quantity>>,
+    units: BTreeMap<String, QuantityUnit>,
+}
+
+impl ConsolidatedBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consolidate snapshots of the venue books, `depth` best levels of each.
+    pub async fn from_books(
+        books: &[(String, SharedOrderBook, QuantityUnit)],
+        depth: Option<usize>,
+    ) -> Self {
+        let mut consolidated = Self::new();
+        for (venue, order_book, unit) in books {
+            consolidated.register(venue, *unit);
+            consolidated.update(venue, &order_book.snapshot(depth).await);
+        }
+        consolidated
+    }
+
+    /// Set the unit of the venue quantities, venues not registered quote the base currency.
+    pub fn register(&mut self, venue: &str, unit: QuantityUnit) {
+        self.units.insert(venue.to_string(), unit);
+    }
+
+    /// Replace all levels of the venue with the snapshot of its book.
+    pub fn update(&mut self, venue: &str, snapshot: &BookSnapshot) {
+        self.remove_venue(venue);
+        let unit = self.units.get(venue).copied().unwrap_or_default();
+        for (levels, side) in [(&snapshot.bids, Side::Buy), (&snapshot.asks, Side::Sell)] {
+            let book = self.side_mut(side);
+            for level in levels.iter().filter(|level| !level.quantity.is_zero()) {
+                book.entry(level.price)
+                    .or_default()
+                    .insert(venue.to_string(), unit.to_base(level.price, level.quantity));
+            }
+        }
+    }
+
+    /// Remove all levels of the venue, e.g. when it disconnects.
+    pub fn remove_venue(&mut self, venue: &str) {
+        for side in [Side::Buy, Side::Sell] {
+            let book = self.side_mut(side);
+            book.retain(|_, venues| {
+                venues.remove(venue);
+                !venues.is_empty()
+            });
+        }
+    }
+
+    /// Levels of the side, best-first.
+    pub fn levels(&self, side: Side) -> Box<dyn Iterator<Item = ConsolidatedLevel> + '_> {
+        let level = |(price, venues): (&Price, &BTreeMap<String, Quantity>)| ConsolidatedLevel {
+            price: *price,
+            quantity: venues.values().copied().sum(),
+            venues: venues
+                .iter()
+                .map(|(venue, quantity)| VenueQuantity {
+                    venue: venue.clone(),
+                    quantity: *quantity,
+                })
+                .collect(),
+        };
+
+        match side {
+            Side::Buy => Box::new(self.bids.iter().rev().map(level)),
+            Side::Sell => Box::new(self.asks.iter().map(level)),
+        }
+    }
+
+    /// Best `n` levels of the side.
+    pub fn top_n(&self, side: Side, n: usize) -> Vec<ConsolidatedLevel> {
+        self.levels(side).take(n).collect()
+    }
+
+    /// Best bid and ask across venues with the venues quoting them.
+    pub fn nbbo(&self) -> Nbbo {
+        Nbbo {
+            bid: self.levels(Side::Buy).next(),
+            ask: self.levels(Side::Sell).next(),
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<Price, BTreeMap<String, Quantity>> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: f64, quantity: f64) -> Level {
+        Level {
+            price: Price::from_f64(price),
+            quantity: Quantity::from_f64(quantity),
+        }
+    }
+
+    fn snapshot(bids: Vec<Level>, asks: Vec<Level>) -> BookSnapshot {
+        BookSnapshot {
+            bids,
+            asks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_consolidate_with_attribution() {
+        let mut book = ConsolidatedBook::new();
+        book.update(
+            "deribit",
+            &snapshot(
+                vec![level(100.0, 1.0), level(99.0, 2.0)],
+                vec![level(101.0, 1.0)],
+            ),
+        );
+        book.update(
+            "bitstamp",
+            &snapshot(vec![level(100.0, 0.5)], vec![level(100.5, 3.0)]),
+        );
+
+        let bids = book.top_n(Side::Buy, 10);
+        assert_eq!(bids.len(), 2);
+        assert_eq!(bids[0].level(), level(100.0, 1.5));
+        assert_eq!(
+            bids[0].venues,
+            vec![
+                VenueQuantity {
+                    venue: "bitstamp".to_string(),
+                    quantity: Quantity::from_f64(0.5)
+                },
+                VenueQuantity {
+                    venue: "deribit".to_string(),
+                    quantity: Quantity::from_f64(1.0)
+                },
+            ]
+        );
+
+        let nbbo = book.nbbo();
+        assert_eq!(nbbo.ask.as_ref().unwrap().price, Price::from_f64(100.5));
+        assert_eq!(nbbo.ask.as_ref().unwrap().venues[0].venue, "bitstamp");
+        assert!(!nbbo.is_crossed());
+    }
+
+    #[test]
+    fn test_update_replaces_venue_levels() {
+        let mut book = ConsolidatedBook::new();
+        book.update("deribit", &snapshot(vec![level(100.0, 1.0)], vec![]));
+        book.update("bitstamp", &snapshot(vec![level(100.0, 2.0)], vec![]));
+        book.update("deribit", &snapshot(vec![level(98.0, 1.0)], vec![]));
+
+        let bids = book.top_n(Side::Buy, 10);
+        assert_eq!(bids[0].level(), level(100.0, 2.0));
+        assert_eq!(bids[1].level(), level(98.0, 1.0));
+
+        book.remove_venue("bitstamp");
+        assert_eq!(book.nbbo().bid.unwrap().level(), level(98.0, 1.0));
+        assert!(book.nbbo().ask.is_none());
+    }
+
+    #[test]
+    fn test_merge_inverse_and_spot_venues() {
+        let mut book = ConsolidatedBook::new();
+        // Deribit BTC-PERPETUAL amounts are in USD, Bitstamp BTC-USD amounts are in BTC
+        book.register("deribit", QuantityUnit::Quote);
+        book.register("bitstamp", QuantityUnit::Base);
+        book.update(
+            "deribit",
+            &snapshot(
+                vec![level(64000.0, 32000.0), level(63999.5, 10.0)],
+                vec![level(64000.5, 128001.0)],
+            ),
+        );
+        book.update(
+            "bitstamp",
+            &snapshot(vec![level(64000.0, 0.25)], vec![level(64000.5, 1.0)]),
+        );
+
+        let bids = book.top_n(Side::Buy, 10);
+        assert_eq!(bids[0].level(), level(64000.0, 0.75));
+        assert_eq!(bids[0].venues[1].quantity, Quantity::from_f64(0.5));
+        // 10 USD is 0.000156251 BTC, rounded to the precision of the quantity
+        assert_eq!(bids[1].quantity.to_string(), "0.00015625");
+        assert_eq!(book.nbbo().ask.unwrap().level(), level(64000.5, 3.0));
+    }
+
+    #[test]
+    fn test_quantity_units() {
+        let price = Price::from_f64(2000.0);
+        let quantity = Quantity::from_f64(3.0);
+        assert_eq!(QuantityUnit::Base.to_base(price, quantity), quantity);
+        assert_eq!(
+            QuantityUnit::Contracts(Quantity::from_f64(0.01)).to_base(price, quantity),
+            Quantity::from_f64(0.03)
+        );
+        assert_eq!(
+            QuantityUnit::Quote.to_base(price, quantity),
+            Quantity::from_f64(0.0015)
+        );
+        // a live level never vanishes from the consolidated book
+        assert_eq!(
+            QuantityUnit::Quote.to_base(Price::from_f64(1e9), Quantity::from_units(1)),
+            Quantity::from_units(1)
+        );
+    }
+
+    #[test]
+    fn test_crossed_venues() {
+        let mut book = ConsolidatedBook::new();
+        book.update("deribit", &snapshot(vec![level(101.0, 1.0)], vec![]));
+        book.update("bitstamp", &snapshot(vec![], vec![level(100.5, 1.0)]));
+
+        assert!(book.nbbo().is_crossed());
+    }
+}
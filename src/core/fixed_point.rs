@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Number of decimal places kept by `Price` and `Quantity`.
+pub const DECIMALS: u32 = 8;
+const SCALE: i64 = 10i64.pow(DECIMALS);
+
+/// Error of parsing a decimal string into a fixed-point value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseFixedError(String);
+
+impl fmt::Display for ParseFixedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid decimal value: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseFixedError {}
+
+/// Parse a decimal string exactly into units of `10^-DECIMALS`.
+fn parse_units(s: &str) -> Result<i64, ParseFixedError> {
+    let error = || ParseFixedError(s.to_string());
+    let text = s.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+
+    let valid = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if (int_part.is_empty() && frac_part.is_empty()) || !valid(int_part) || !valid(frac_part) {
+        return Err(error());
+    }
+
+    // digits beyond the supported precision must be zeros, e.g. "1.000000000"
+    let (frac_part, rest) = frac_part.split_at(frac_part.len().min(DECIMALS as usize));
+    if rest.bytes().any(|b| b != b'0') {
+        return Err(error());
+    }
+
+    let int_units = if int_part.is_empty() {
+        0
+    } else {
+        int_part.parse::<i64>().map_err(|_| error())?
+    };
+    let frac_units = if frac_part.is_empty() {
+        0
+    } else {
+        let padding = 10i64.pow(DECIMALS - frac_part.len() as u32);
+        frac_part.parse::<i64>().map_err(|_| error())? * padding
+    };
+
+    let units = int_units
+        .checked_mul(SCALE)
+        .and_then(|units| units.checked_add(frac_units))
+        .ok_or_else(error)?;
+
+    Ok(if negative { -units } else { units })
+}
+
+/// Format units of `10^-DECIMALS` without trailing zeros.
+fn format_units(units: i64, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let sign = if units < 0 { "-" } else { "" };
+    let units = units.unsigned_abs();
+    let int_part = units / SCALE as u64;
+    let frac_part = units % SCALE as u64;
+
+    if frac_part == 0 {
+        return write!(f, "{}{}", sign, int_part);
+    }
+    let frac = format!("{:0width$}", frac_part, width = DECIMALS as usize);
+    write!(f, "{}{}.{}", sign, int_part, frac.trim_end_matches('0'))
+}
+
+/// Parse a decimal string exactly, an error unless it is a multiple of the step.
+fn parse_on_grid(text: &str, step: i64) -> Result<i64, ParseFixedError> {
+    let units = parse_units(text)?;
+    if step > 1 && units % step != 0 {
+        return Err(ParseFixedError(format!(
+            "{} (not a multiple of {})",
+            text.trim(),
+            Price::from_units(step)
+        )));
+    }
+    Ok(units)
+}
+
+macro_rules! fixed_point {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(i64);
+
+        impl $name {
+            pub const ZERO: $name = $name(0);
+
+            /// Value from units of `10^-DECIMALS`.
+            pub const fn from_units(units: i64) -> Self {
+                $name(units)
+            }
+
+            /// Units of `10^-DECIMALS`.
+            pub const fn units(self) -> i64 {
+                self.0
+            }
+
+            /// Nearest fixed-point value, saturating out of range values.
+            pub fn from_f64(value: f64) -> Self {
+                $name((value * SCALE as f64).round() as i64)
+            }
+
+            pub fn to_f64(self) -> f64 {
+                self.0 as f64 / SCALE as f64
+            }
+
+            pub fn is_zero(self) -> bool {
+                self.0 == 0
+            }
+
+            /// Number of whole steps in the value, e.g. price in ticks.
+            pub fn steps(self, step: $name) -> i64 {
+                if step.0 == 0 {
+                    self.0
+                } else {
+                    self.0 / step.0
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseFixedError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                parse_units(s).map($name)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                format_units(self.0, f)
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+
+            fn add(self, other: $name) -> $name {
+                $name(self.0 + other.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: $name) {
+                self.0 += other.0;
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+
+            fn sub(self, other: $name) -> $name {
+                $name(self.0 - other.0)
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, other: $name) {
+                self.0 -= other.0;
+            }
+        }
+
+        impl Sum for $name {
+            fn sum<I: Iterator<Item = $name>>(iter: I) -> $name {
+                iter.fold($name::ZERO, Add::add)
+            }
+        }
+
+        /// Exact decimal string, e.g. `"64000.5"`, a float would not keep every value.
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_any(FixedVisitor).map($name)
+            }
+        }
+    };
+}
+
+fixed_point!(
+    /// Exact price with `DECIMALS` decimal places.
+    Price
+);
+
+fixed_point!(
+    /// Exact quantity with `DECIMALS` decimal places.
+    Quantity
+);
+
+/// Accepts JSON/TOML numbers and decimal strings.
+struct FixedVisitor;
+
+impl de::Visitor<'_> for FixedVisitor {
+    type Value = i64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a decimal number or string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<i64, E> {
+        parse_units(v).map_err(E::custom)
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<i64, E> {
+        // the shortest representation of the number is the decimal it was written as
+        parse_units(&v.to_string()).map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<i64, E> {
+        v.checked_mul(SCALE)
+            .ok_or_else(|| E::custom(format!("Decimal value out of range: {}", v)))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<i64, E> {
+        i64::try_from(v)
+            .ok()
+            .and_then(|v| v.checked_mul(SCALE))
+            .ok_or_else(|| E::custom(format!("Decimal value out of range: {}", v)))
+    }
+}
+
+/// Price and quantity grid of an instrument.
+///
+/// Venue values are parsed exactly and must be multiples of the tick size and lot size.
+/// Values off the grid are rejected rather than rounded, rounding would merge distinct
+/// prices into one level and turn small quantities into deletes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstrumentSpec {
+    pub tick_size: Price,
+    pub lot_size: Quantity,
+}
+
+impl InstrumentSpec {
+    pub fn new(tick_size: Price, lot_size: Quantity) -> Self {
+        InstrumentSpec {
+            tick_size,
+            lot_size,
+        }
+    }
+
+    /// Price of a JSON number, as the decimal it was written as.
+    pub fn price(&self, value: f64) -> Result<Price, ParseFixedError> {
+        self.parse_price(&value.to_string())
+    }
+
+    /// Quantity of a JSON number, as the decimal it was written as.
+    pub fn quantity(&self, value: f64) -> Result<Quantity, ParseFixedError> {
+        self.parse_quantity(&value.to_string())
+    }
+
+    pub fn parse_price(&self, text: &str) -> Result<Price, ParseFixedError> {
+        parse_on_grid(text, self.tick_size.units()).map(Price::from_units)
+    }
+
+    pub fn parse_quantity(&self, text: &str) -> Result<Quantity, ParseFixedError> {
+        parse_on_grid(text, self.lot_size.units()).map(Quantity::from_units)
+    }
+}
+
+impl Default for InstrumentSpec {
+    /// The finest grid supported by the fixed-point types.
+    fn default() -> Self {
+        InstrumentSpec {
+            tick_size: Price::from_units(1),
+            lot_size: Quantity::from_units(1),
+        }
+    }
+}
+
+/// Grids of the instruments of a provider, looked up by the instrument of every message.
+///
+/// Keyed by the normalized instrument, e.g. `btcperpetual`, instruments not listed use
+/// the default grid.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstrumentSpecs {
+    default: InstrumentSpec,
+    instruments: HashMap<String, InstrumentSpec>,
+}
+
+impl InstrumentSpecs {
+    pub fn new(default: InstrumentSpec) -> Self {
+        InstrumentSpecs {
+            default,
+            instruments: HashMap::new(),
+        }
+    }
+
+    /// Set the grid of the normalized instrument.
+    pub fn insert(&mut self, instrument: impl Into<String>, spec: InstrumentSpec) {
+        self.instruments.insert(instrument.into(), spec);
+    }
+
+    /// Grid of the normalized instrument.
+    pub fn get(&self, instrument: &str) -> &InstrumentSpec {
+        self.instruments.get(instrument).unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(
+            "64000.5".parse::<Price>().unwrap().units(),
+            6_400_050_000_000
+        );
+        assert_eq!("0.00000001".parse::<Quantity>().unwrap().units(), 1);
+        assert_eq!(".5".parse::<Price>().unwrap().to_string(), "0.5");
+        assert_eq!("-1.2500".parse::<Price>().unwrap().to_string(), "-1.25");
+        assert_eq!("100".parse::<Price>().unwrap().to_string(), "100");
+        assert_eq!("1.0000000000".parse::<Price>().unwrap().to_string(), "1");
+
+        assert!("".parse::<Price>().is_err());
+        assert!("abc".parse::<Price>().is_err());
+        assert!("1.000000001".parse::<Price>().is_err());
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        let a: Quantity = "0.1".parse().unwrap();
+        let b: Quantity = "0.2".parse().unwrap();
+        assert_eq!(a + b, "0.3".parse().unwrap());
+        assert_eq!(
+            vec![a, b, a].into_iter().sum::<Quantity>().to_string(),
+            "0.4"
+        );
+    }
+
+    #[test]
+    fn test_instrument_grid() {
+        let spec = InstrumentSpec::new("0.5".parse().unwrap(), "10".parse().unwrap());
+
+        assert_eq!(spec.price(64000.5).unwrap().to_string(), "64000.5");
+        assert_eq!(spec.parse_price("64000.00").unwrap().to_string(), "64000");
+        assert_eq!(spec.quantity(120.0).unwrap().to_string(), "120");
+        assert_eq!(spec.price(64000.5).unwrap().steps(spec.tick_size), 128001);
+
+        // off the grid values are not rounded onto a neighbouring level
+        assert!(spec.price(64000.2).is_err());
+        assert!(spec.parse_price("64000.74").is_err());
+        // a live level never becomes a delete
+        assert!(spec.quantity(4.0).is_err());
+        assert_eq!(
+            spec.parse_price("5042.34").unwrap_err().to_string(),
+            "Invalid decimal value: 5042.34 (not a multiple of 0.5)"
+        );
+
+        // the finest grid takes any decimal the fixed-point types can hold
+        let default = InstrumentSpec::default();
+        assert_eq!(default.price(5042.34).unwrap().to_string(), "5042.34");
+        assert_eq!(default.quantity(0.00000001).unwrap().units(), 1);
+        assert!(default.quantity(0.000000001).is_err());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let values: Vec<Price> = ["101", "0.1", "64000.12345678", "92233720368.54775807"]
+            .iter()
+            .map(|value| value.parse().unwrap())
+            .collect();
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!(
+            json,
+            r#"["101","0.1","64000.12345678","92233720368.54775807"]"#
+        );
+        assert_eq!(serde_json::from_str::<Vec<Price>>(&json).unwrap(), values);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let prices: Vec<Price> = serde_json::from_str(r#"[0.1, "0.2", 3]"#).unwrap();
+        assert_eq!(prices[0].to_string(), "0.1");
+        assert_eq!(prices[1].to_string(), "0.2");
+        assert_eq!(prices[2].to_string(), "3");
+    }
+}
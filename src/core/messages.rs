@@ -1,6 +1,8 @@
 use serde::Deserialize;
 
-#[derive(Debug, Clone, Deserialize)]
+use super::fixed_point::{Price, Quantity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 pub enum Side {
     Buy,
     Sell,
@@ -8,8 +10,8 @@ pub enum Side {
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct OrderBookUpdate {
-    pub price: f64,
-    pub quantity: f64,
+    pub price: Price,
+    pub quantity: Quantity,
     pub side: Side,
 }
 
@@ -17,6 +19,32 @@ pub struct OrderBookUpdate {
 pub struct OrderBookSnapshot {
     pub bids: Vec<OrderBookUpdate>,
     pub asks: Vec<OrderBookUpdate>,
+    /// Exchange sequence (change id) of the snapshot, if the feed is sequenced.
+    #[serde(default)]
+    pub sequence: Option<u64>,
+}
+
+/// Batch of level updates, zero quantity means delete the level.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrderBookDelta {
+    pub updates: Vec<OrderBookUpdate>,
+    /// Exchange sequence (change id) of the delta.
+    pub sequence: u64,
+    /// Sequence of the previous delta, for feeds which publish it to detect gaps.
+    #[serde(default)]
+    pub prev_sequence: Option<u64>,
+}
+
+/// Public trade of the venue.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Trade {
+    pub price: Price,
+    pub quantity: Quantity,
+    /// Side of the taker, `Buy` when the trade lifted an ask.
+    pub aggressor: Side,
+    pub trade_id: String,
+    /// Exchange time of the trade, in microseconds since the Unix epoch.
+    pub timestamp_us: u64,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -24,4 +52,9 @@ pub struct OrderBookSnapshot {
 pub enum Message {
     Update(OrderBookUpdate),
     Snapshot(OrderBookSnapshot),
+    Delta(OrderBookDelta),
+    /// Trades of a single venue message, oldest first.
+    // a sequence cannot be read with the internal tag, trades are produced by the providers only
+    #[serde(skip_deserializing)]
+    Trades(Vec<Trade>),
 }
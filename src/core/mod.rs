@@ -1,59 +1,327 @@
+pub mod analytics;
+pub mod consolidated;
+pub mod fixed_point;
 pub mod messages;
 pub mod order_book;
+pub mod trades;
 
-use messages::OrderBookUpdate;
-use order_book::OrderBook;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use fixed_point::{Price, Quantity};
+use messages::{OrderBookDelta, OrderBookSnapshot, OrderBookUpdate, Side};
+use order_book::{BookSnapshot, BookStats, CrossState, Level, OrderBook, SequenceStatus};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{watch, RwLock};
+use trades::SharedTrades;
 
 /// A shared for thread-safety read-write lock for the order book.
 #[derive(Clone)]
 pub struct SharedOrderBook {
     inner: Arc<RwLock<OrderBook>>,
+    version: Arc<watch::Sender<u64>>,
+    updated: Arc<Mutex<Option<Instant>>>,
+    stale: Arc<AtomicBool>,
+    trades: SharedTrades,
 }
 
 impl SharedOrderBook {
     pub fn initialise(depth_limit: usize) -> Self {
+        Self::with_depth_limit(Some(depth_limit))
+    }
+
+    /// Book trimmed to `depth_limit` levels per side, or keeping every level,
+    /// see `OrderBook::with_depth_limit`.
+    pub fn with_depth_limit(depth_limit: Option<usize>) -> Self {
+        let (version, _) = watch::channel(0);
         SharedOrderBook {
-            inner: Arc::new(RwLock::new(OrderBook::new(depth_limit))),
+            inner: Arc::new(RwLock::new(OrderBook::with_depth_limit(depth_limit))),
+            version: Arc::new(version),
+            updated: Arc::new(Mutex::new(None)),
+            stale: Arc::new(AtomicBool::new(false)),
+            trades: SharedTrades::default(),
         }
     }
 
+    /// Recent public trades of the instrument, fed when the provider streams trades.
+    pub fn trades(&self) -> &SharedTrades {
+        &self.trades
+    }
+
+    /// Notifications with the version of the book, incremented on every applied change.
+    ///
+    /// Changes are conflated: a slow receiver sees only the latest version.
+    pub fn changes(&self) -> watch::Receiver<u64> {
+        self.version.subscribe()
+    }
+
+    /// Subscribe to snapshots of the best `depth` levels taken after every change.
+    pub fn subscribe(&self, depth: Option<usize>) -> BookSubscription {
+        let mut changes = self.changes();
+        let version = *changes.borrow_and_update();
+        BookSubscription {
+            order_book: self.clone(),
+            changes,
+            depth,
+            version,
+            skipped: 0,
+        }
+    }
+
+    fn notify(&self) {
+        *self.updated.lock().unwrap() = Some(Instant::now());
+        self.version.send_modify(|version| *version += 1);
+    }
+
+    /// Data of the venue was applied, the book is not stale anymore.
+    fn refresh(&self) {
+        self.stale.store(false, Ordering::Relaxed);
+        self.notify();
+    }
+
+    /// Mark the book as not trusted because its feed stopped, until data is applied again.
+    pub fn mark_stale(&self) {
+        self.stale.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::Relaxed)
+    }
+
+    /// Time since the last change of the book, `None` before the first one.
+    pub fn last_update_age(&self) -> Option<Duration> {
+        self.updated
+            .lock()
+            .unwrap()
+            .map(|updated| updated.elapsed())
+    }
+
     pub async fn process_snapshot(&self, bids: Vec<OrderBookUpdate>, asks: Vec<OrderBookUpdate>) {
         let mut order_book = self.inner.write().await;
         order_book.process_snapshot(bids, asks);
+        self.refresh();
+    }
+
+    pub async fn process_update(&self, update: OrderBookUpdate) {
+        let mut order_book = self.inner.write().await;
+        order_book.process_update(update);
+        self.refresh();
     }
 
-    pub async fn get_best_bid(&self) -> Option<f64> {
+    pub async fn apply_snapshot(&self, snapshot: OrderBookSnapshot) {
+        let mut order_book = self.inner.write().await;
+        order_book.apply_snapshot(snapshot);
+        self.refresh();
+    }
+
+    pub async fn apply_delta(&self, delta: OrderBookDelta) -> SequenceStatus {
+        let mut order_book = self.inner.write().await;
+        let status = order_book.apply_delta(delta);
+        match status {
+            SequenceStatus::Applied => self.refresh(),
+            // the levels were dropped until the resync
+            SequenceStatus::Gap { .. } => self.notify(),
+            SequenceStatus::Stale | SequenceStatus::AwaitingSnapshot => {}
+        }
+        status
+    }
+
+    pub async fn get_sequence(&self) -> Option<u64> {
+        let order_book = self.inner.read().await;
+        order_book.sequence
+    }
+
+    pub async fn reset(&self) {
+        let mut order_book = self.inner.write().await;
+        order_book.reset();
+        self.notify();
+    }
+
+    pub async fn get_best_bid(&self) -> Option<Price> {
         let order_book = self.inner.read().await;
         order_book.get_best_bid()
     }
 
-    pub async fn get_best_ask(&self) -> Option<f64> {
+    pub async fn get_best_ask(&self) -> Option<Price> {
         let order_book = self.inner.read().await;
         order_book.get_best_ask()
     }
 
+    pub async fn cross_state(&self) -> CrossState {
+        let order_book = self.inner.read().await;
+        order_book.cross_state()
+    }
+
+    /// Drop the crossing levels of both sides, see `OrderBook::purge_crossing`.
+    pub async fn purge_crossing(&self) -> usize {
+        let mut order_book = self.inner.write().await;
+        let purged = order_book.purge_crossing();
+        if purged > 0 {
+            self.notify();
+        }
+        purged
+    }
+
     pub async fn get_used_depth_limit(&self) -> usize {
         let order_book = self.inner.read().await;
         order_book.asks.len()
     }
 
-    pub async fn get_bids(&self) -> Vec<f64> {
+    pub async fn get_bids(&self) -> Vec<Price> {
+        let order_book = self.inner.read().await;
+        order_book.bids.keys().copied().collect::<Vec<_>>()
+    }
+
+    pub async fn get_asks(&self) -> Vec<Price> {
+        let order_book = self.inner.read().await;
+        order_book.asks.keys().copied().collect::<Vec<_>>()
+    }
+
+    /// Bid levels with quantities, best (highest) first.
+    pub async fn get_bid_levels(&self) -> Vec<Level> {
+        let order_book = self.inner.read().await;
+        order_book.levels(Side::Buy).collect()
+    }
+
+    /// Ask levels with quantities, best (lowest) first.
+    pub async fn get_ask_levels(&self) -> Vec<Level> {
+        let order_book = self.inner.read().await;
+        order_book.levels(Side::Sell).collect()
+    }
+
+    pub async fn top_n(&self, side: Side, n: usize) -> Vec<Level> {
+        let order_book = self.inner.read().await;
+        order_book.top_n(side, n)
+    }
+
+    pub async fn level_at(&self, price: Price) -> Option<(Side, Quantity)> {
+        let order_book = self.inner.read().await;
+        order_book.level_at(price)
+    }
+
+    /// Depth and top of the book, cheap enough for every metrics scrape.
+    pub async fn stats(&self) -> BookStats {
         let order_book = self.inner.read().await;
-        order_book
-            .bids
-            .keys()
-            .map(|k| k.into_inner())
-            .collect::<Vec<_>>()
+        order_book.stats()
     }
 
-    pub async fn get_asks(&self) -> Vec<f64> {
+    /// Both sides of the book taken under a single lock, limited to `depth` levels per side.
+    pub async fn snapshot(&self, depth: Option<usize>) -> BookSnapshot {
         let order_book = self.inner.read().await;
-        order_book
-            .asks
-            .keys()
-            .map(|k| k.into_inner())
-            .collect::<Vec<_>>()
+        order_book.snapshot(depth)
+    }
+}
+
+/// Stream of book snapshots, one per observed change.
+///
+/// Lag policy is conflation: when the consumer is slower than the feed, intermediate
+/// changes are skipped and the next snapshot reflects the latest state.
+pub struct BookSubscription {
+    order_book: SharedOrderBook,
+    changes: watch::Receiver<u64>,
+    depth: Option<usize>,
+    version: u64,
+    skipped: u64,
+}
+
+impl BookSubscription {
+    /// Wait for the next change of the book and take its snapshot.
+    ///
+    /// Cancel safe: a change is marked as seen only once its snapshot is taken, so a call
+    /// dropped in `select!` does not lose it.
+    pub async fn next(&mut self) -> BookSnapshot {
+        // `changed` marks the version as seen, compare with the last snapshot instead
+        while *self.changes.borrow() == self.version {
+            // the sender lives as long as the order book held by the subscription
+            let _ = self.changes.changed().await;
+        }
+        let order_book = self.order_book.inner.read().await;
+        // versions are bumped under the write lock, this one matches the state read here
+        let version = *self.changes.borrow();
+        self.skipped += version.saturating_sub(self.version + 1);
+        self.version = version;
+
+        order_book.snapshot(self.depth)
+    }
+
+    /// Version of the book seen by the last snapshot.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Number of changes conflated so far because the consumer lagged behind.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bid(price: f64) -> OrderBookUpdate {
+        OrderBookUpdate {
+            price: Price::from_f64(price),
+            quantity: Quantity::from_f64(1.0),
+            side: Side::Buy,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscription_conflates_changes() {
+        let order_book = SharedOrderBook::initialise(10);
+        let mut subscription = order_book.subscribe(None);
+
+        order_book.process_update(bid(100.0)).await;
+        let snapshot = subscription.next().await;
+        assert_eq!(snapshot.best_bid, Some(Price::from_f64(100.0)));
+        assert_eq!(subscription.version(), 1);
+
+        order_book.process_update(bid(101.0)).await;
+        order_book.process_update(bid(102.0)).await;
+        let snapshot = subscription.next().await;
+        assert_eq!(snapshot.bids.len(), 3);
+        assert_eq!(subscription.version(), 3);
+        assert_eq!(subscription.skipped(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_subscription_is_cancel_safe() {
+        let order_book = SharedOrderBook::initialise(10);
+        let mut subscription = order_book.subscribe(None);
+        order_book.process_update(bid(100.0)).await;
+
+        // cancelled while waiting for the book, e.g. by another branch of a select
+        let writer = order_book.inner.write().await;
+        let cancelled = tokio::time::timeout(Duration::from_millis(10), subscription.next()).await;
+        assert!(cancelled.is_err());
+        drop(writer);
+
+        // the change is still pending
+        let snapshot = tokio::time::timeout(Duration::from_millis(100), subscription.next())
+            .await
+            .expect("change lost by the cancelled call");
+        assert_eq!(snapshot.best_bid, Some(Price::from_f64(100.0)));
+        assert_eq!(subscription.version(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_skipped_delta_does_not_notify() {
+        let order_book = SharedOrderBook::initialise(10);
+        let changes = order_book.changes();
+
+        let status = order_book
+            .apply_delta(OrderBookDelta {
+                updates: vec![bid(100.0)],
+                sequence: 1,
+                prev_sequence: None,
+            })
+            .await;
+
+        assert_eq!(status, SequenceStatus::AwaitingSnapshot);
+        assert!(!changes.has_changed().unwrap());
     }
 }
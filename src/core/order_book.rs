@@ -1,14 +1,101 @@
 use log::{debug, info, warn};
-use ordered_float::OrderedFloat;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
-use super::messages::{OrderBookUpdate, Side};
+use super::fixed_point::{Price, Quantity};
+use super::messages::{OrderBookDelta, OrderBookSnapshot, OrderBookUpdate, Side};
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Order {
-    pub price: f64,
-    pub quantity: f64,
+    pub price: Price,
+    pub quantity: Quantity,
+}
+
+/// Aggregated quantity at a price level.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Level {
+    pub price: Price,
+    pub quantity: Quantity,
+}
+
+impl From<&Order> for Level {
+    fn from(order: &Order) -> Self {
+        Level {
+            price: order.price,
+            quantity: order.quantity,
+        }
+    }
+}
+
+/// Consistent copy of both sides of the book, levels are ordered best-first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BookSnapshot {
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+    pub best_bid: Option<Price>,
+    pub best_ask: Option<Price>,
+    pub sequence: Option<u64>,
+}
+
+impl BookSnapshot {
+    pub fn cross_state(&self) -> CrossState {
+        CrossState::of(self.best_bid, self.best_ask)
+    }
+}
+
+/// Depth and top of the book, read without copying the levels.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BookStats {
+    pub bid_depth: usize,
+    pub ask_depth: usize,
+    pub best_bid: Option<Price>,
+    pub best_ask: Option<Price>,
+}
+
+impl BookStats {
+    /// Best ask minus best bid.
+    pub fn spread(&self) -> Option<Price> {
+        Some(self.best_ask? - self.best_bid?)
+    }
+}
+
+/// Relation of the best bid to the best ask, a valid book is never crossed or locked.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CrossState {
+    /// Best bid below the best ask, or one side is empty.
+    #[default]
+    Normal,
+    /// Best bid equal to the best ask.
+    Locked,
+    /// Best bid above the best ask.
+    Crossed,
+}
+
+impl CrossState {
+    pub fn of(best_bid: Option<Price>, best_ask: Option<Price>) -> Self {
+        match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) if bid > ask => CrossState::Crossed,
+            (Some(bid), Some(ask)) if bid == ask => CrossState::Locked,
+            _ => CrossState::Normal,
+        }
+    }
+
+    pub fn is_normal(self) -> bool {
+        self == CrossState::Normal
+    }
+}
+
+/// Result of applying a sequenced delta to the order book.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SequenceStatus {
+    /// The delta was applied.
+    Applied,
+    /// The delta is older than the book state and was skipped.
+    Stale,
+    /// A delta is missing, the book is emptied until the next snapshot.
+    Gap { expected: u64, received: u64 },
+    /// No sequenced snapshot was applied yet, the delta was skipped.
+    AwaitingSnapshot,
 }
 
 /*
@@ -17,26 +104,38 @@ pub struct Order {
 */
 #[derive(Debug, Clone)]
 pub struct OrderBook {
-    pub bids: BTreeMap<OrderedFloat<f64>, Order>,
-    pub asks: BTreeMap<OrderedFloat<f64>, Order>,
-    pub best_bid: Option<f64>,
-    pub best_ask: Option<f64>,
-    pub depth_limit: usize,
+    pub bids: BTreeMap<Price, Order>,
+    pub asks: BTreeMap<Price, Order>,
+    pub best_bid: Option<Price>,
+    pub best_ask: Option<Price>,
+    /// Levels kept per side, every level if not set.
+    pub depth_limit: Option<usize>,
+    /// Exchange sequence of the last applied snapshot or delta.
+    pub sequence: Option<u64>,
 }
 
 impl OrderBook {
     pub fn new(depth_limit: usize) -> Self {
+        Self::with_depth_limit(Some(depth_limit))
+    }
+
+    /// Book trimmed to `depth_limit` levels per side, or keeping every level.
+    ///
+    /// Books fed by changes must keep every level: a trimmed level is never sent again,
+    /// so it would be missing once the levels above it are deleted.
+    pub fn with_depth_limit(depth_limit: Option<usize>) -> Self {
         OrderBook {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
             best_bid: None,
             best_ask: None,
             depth_limit,
+            sequence: None,
         }
     }
 
     pub fn add_order(&mut self, order: Order, side: Side) {
-        let price = OrderedFloat(order.price);
+        let price = order.price;
         match side {
             Side::Buy => {
                 self.bids.insert(price, order);
@@ -53,18 +152,17 @@ impl OrderBook {
         }
     }
 
-    pub fn remove_order(&mut self, price: f64, side: Side) {
-        let price = OrderedFloat(price);
+    pub fn remove_order(&mut self, price: Price, side: Side) {
         match side {
             Side::Buy => {
                 self.bids.remove(&price);
                 self.update_best_bid();
-                warn!("Removed order from bids at price: {:?}", price);
+                debug!("Removed order from bids at price: {:?}", price);
             }
             Side::Sell => {
                 self.asks.remove(&price);
                 self.update_best_ask();
-                warn!("Removed order from asks at price: {:?}", price);
+                debug!("Removed order from asks at price: {:?}", price);
             }
         }
     }
@@ -99,18 +197,89 @@ impl OrderBook {
         debug!("Order book depth limit: {}", self.bids.len());
     }
 
+    /// Apply a single level update, zero quantity removes the level.
+    pub fn process_update(&mut self, update: OrderBookUpdate) {
+        if update.quantity.is_zero() {
+            self.remove_order(update.price, update.side);
+        } else {
+            let order = Order {
+                price: update.price,
+                quantity: update.quantity,
+            };
+            self.add_order(order, update.side);
+        }
+    }
+
+    /// Drop all levels, e.g. when the connection to the provider is lost.
+    pub fn reset(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.best_bid = None;
+        self.best_ask = None;
+        self.sequence = None;
+    }
+
+    /// Replace the book with the snapshot and continue from its sequence.
+    pub fn apply_snapshot(&mut self, snapshot: OrderBookSnapshot) {
+        self.process_snapshot(snapshot.bids, snapshot.asks);
+        self.sequence = snapshot.sequence;
+    }
+
+    /// Apply the delta if it continues the current sequence.
+    ///
+    /// Feeds publishing `prev_sequence` must chain to the last applied sequence exactly,
+    /// other feeds only need a growing sequence. On a gap the levels are dropped, as the
+    /// book is known to be wrong, and further deltas are skipped until a new snapshot is applied.
+    pub fn apply_delta(&mut self, delta: OrderBookDelta) -> SequenceStatus {
+        let Some(sequence) = self.sequence else {
+            return SequenceStatus::AwaitingSnapshot;
+        };
+
+        if delta.sequence <= sequence {
+            debug!(
+                "Skipped stale delta {} at sequence {}",
+                delta.sequence, sequence
+            );
+            return SequenceStatus::Stale;
+        }
+
+        if let Some(prev_sequence) = delta.prev_sequence {
+            if prev_sequence != sequence {
+                warn!(
+                    "Sequence gap: expected {}, received {}",
+                    sequence, prev_sequence
+                );
+                self.reset();
+                return SequenceStatus::Gap {
+                    expected: sequence,
+                    received: prev_sequence,
+                };
+            }
+        }
+
+        for update in delta.updates {
+            self.process_update(update);
+        }
+        self.sequence = Some(delta.sequence);
+
+        SequenceStatus::Applied
+    }
+
     fn update_best_bid(&mut self) {
-        self.best_bid = self.bids.keys().next_back().map(|p| p.into_inner()); // get the price of the highest bid
+        self.best_bid = self.bids.keys().next_back().copied(); // get the price of the highest bid
     }
 
     fn update_best_ask(&mut self) {
-        self.best_ask = self.asks.keys().next().map(|p| p.into_inner()); // get the price of the lowest ask
+        self.best_ask = self.asks.keys().next().copied(); // get the price of the lowest ask
     }
 
     fn enforce_depth_limit(&mut self, side: Side) {
+        let Some(depth_limit) = self.depth_limit else {
+            return;
+        };
         match side {
             Side::Buy => {
-                while self.bids.len() > self.depth_limit {
+                while self.bids.len() > depth_limit {
                     let lowest_bid = self.bids.keys().next().cloned().unwrap(); // remove less competetive bid from the top
                     self.bids.remove(&lowest_bid);
                     debug!(
@@ -120,7 +289,7 @@ impl OrderBook {
                 }
             }
             Side::Sell => {
-                while self.asks.len() > self.depth_limit {
+                while self.asks.len() > depth_limit {
                     let highest_ask = self.asks.keys().next_back().cloned().unwrap(); // remove more competetive ask from the bottom
                     self.asks.remove(&highest_ask);
                     debug!(
@@ -132,40 +301,123 @@ impl OrderBook {
         }
     }
 
-    pub fn get_best_bid(&self) -> Option<f64> {
+    pub fn get_best_bid(&self) -> Option<Price> {
         self.best_bid
     }
 
-    pub fn get_best_ask(&self) -> Option<f64> {
+    pub fn get_best_ask(&self) -> Option<Price> {
         self.best_ask
     }
+
+    /// Levels of the side ordered best-first: bids descending, asks ascending.
+    pub fn levels(&self, side: Side) -> Box<dyn Iterator<Item = Level> + '_> {
+        match side {
+            Side::Buy => Box::new(self.bids.values().rev().map(Level::from)),
+            Side::Sell => Box::new(self.asks.values().map(Level::from)),
+        }
+    }
+
+    /// Best `n` levels of the side.
+    pub fn top_n(&self, side: Side, n: usize) -> Vec<Level> {
+        self.levels(side).take(n).collect()
+    }
+
+    /// Side and quantity of the level at the price, if any.
+    pub fn level_at(&self, price: Price) -> Option<(Side, Quantity)> {
+        self.bids
+            .get(&price)
+            .map(|order| (Side::Buy, order.quantity))
+            .or_else(|| {
+                self.asks
+                    .get(&price)
+                    .map(|order| (Side::Sell, order.quantity))
+            })
+    }
+
+    pub fn cross_state(&self) -> CrossState {
+        CrossState::of(self.best_bid, self.best_ask)
+    }
+
+    /// Drop the levels of both sides within the crossed range, i.e. bids at or above
+    /// the best ask and asks at or below the best bid. Returns the number of dropped levels.
+    pub fn purge_crossing(&mut self) -> usize {
+        let (Some(best_bid), Some(best_ask)) = (self.best_bid, self.best_ask) else {
+            return 0;
+        };
+        if best_bid < best_ask {
+            return 0;
+        }
+
+        let levels = self.bids.len() + self.asks.len();
+        self.bids.retain(|price, _| *price < best_ask);
+        self.asks.retain(|price, _| *price > best_bid);
+        self.update_best_bid();
+        self.update_best_ask();
+
+        let purged = levels - self.bids.len() - self.asks.len();
+        warn!(
+            "Purged {} crossing levels between {} and {}",
+            purged, best_ask, best_bid
+        );
+        purged
+    }
+
+    /// Copy of the best `depth` levels of both sides, all levels if not set.
+    pub fn snapshot(&self, depth: Option<usize>) -> BookSnapshot {
+        let depth = depth.unwrap_or(usize::MAX);
+        BookSnapshot {
+            bids: self.top_n(Side::Buy, depth),
+            asks: self.top_n(Side::Sell, depth),
+            best_bid: self.best_bid,
+            best_ask: self.best_ask,
+            sequence: self.sequence,
+        }
+    }
+
+    pub fn stats(&self) -> BookStats {
+        BookStats {
+            bid_depth: self.bids.len(),
+            ask_depth: self.asks.len(),
+            best_bid: self.best_bid,
+            best_ask: self.best_ask,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::config::{ExchangeConfig, Feed};
+
+    fn price(value: f64) -> Price {
+        Price::from_f64(value)
+    }
+
+    fn quantity(value: f64) -> Quantity {
+        Quantity::from_f64(value)
+    }
 
     #[test]
     fn test_add_order() {
         let mut order_book = OrderBook::new(10);
         let order = Order {
-            price: 100.0,
-            quantity: 1.0,
+            price: price(100.0),
+            quantity: quantity(1.0),
         };
         order_book.add_order(order.clone(), Side::Buy);
         assert_eq!(order_book.bids.len(), 1);
-        assert_eq!(order_book.bids[&OrderedFloat(100.0)], order);
+        assert_eq!(order_book.bids[&price(100.0)], order);
     }
 
     #[test]
     fn test_remove_order() {
         let mut order_book = OrderBook::new(10);
         let order = Order {
-            price: 100.0,
-            quantity: 1.0,
+            price: price(100.0),
+            quantity: quantity(1.0),
         };
         order_book.add_order(order.clone(), Side::Buy);
-        order_book.remove_order(100.0, Side::Buy);
+        order_book.remove_order(price(100.0), Side::Buy);
 
         assert_eq!(order_book.bids.len(), 0);
     }
@@ -175,25 +427,25 @@ mod tests {
         let mut order_book = OrderBook::new(10);
         let bids = vec![
             OrderBookUpdate {
-                price: 100.0,
-                quantity: 1.0,
+                price: price(100.0),
+                quantity: quantity(1.0),
                 side: Side::Buy,
             },
             OrderBookUpdate {
-                price: 101.0,
-                quantity: 2.0,
+                price: price(101.0),
+                quantity: quantity(2.0),
                 side: Side::Buy,
             },
         ];
         let asks = vec![
             OrderBookUpdate {
-                price: 102.0,
-                quantity: 1.0,
+                price: price(102.0),
+                quantity: quantity(1.0),
                 side: Side::Sell,
             },
             OrderBookUpdate {
-                price: 103.0,
-                quantity: 2.0,
+                price: price(103.0),
+                quantity: quantity(2.0),
                 side: Side::Sell,
             },
         ];
@@ -202,32 +454,266 @@ mod tests {
 
         assert_eq!(order_book.bids.len(), 2);
         assert_eq!(order_book.asks.len(), 2);
-        assert_eq!(order_book.best_bid, Some(101.0));
-        assert_eq!(order_book.best_ask, Some(102.0));
+        assert_eq!(order_book.best_bid, Some(price(101.0)));
+        assert_eq!(order_book.best_ask, Some(price(102.0)));
     }
 
     #[test]
     fn test_enforce_depth_limit() {
         let mut order_book = OrderBook::new(2);
         let order1 = Order {
-            price: 100.0,
-            quantity: 1.0,
+            price: price(100.0),
+            quantity: quantity(1.0),
         };
         let order2 = Order {
-            price: 101.0,
-            quantity: 1.0,
+            price: price(101.0),
+            quantity: quantity(1.0),
         };
         let order3 = Order {
-            price: 102.0,
-            quantity: 1.0,
+            price: price(102.0),
+            quantity: quantity(1.0),
         };
         order_book.add_order(order1, Side::Buy);
         order_book.add_order(order2, Side::Buy);
         order_book.add_order(order3, Side::Buy);
 
         assert_eq!(order_book.bids.len(), 2);
-        assert!(order_book.bids.contains_key(&OrderedFloat(101.0)));
-        assert!(order_book.bids.contains_key(&OrderedFloat(102.0)));
-        assert!(!order_book.bids.contains_key(&OrderedFloat(100.0)));
+        assert!(order_book.bids.contains_key(&price(101.0)));
+        assert!(order_book.bids.contains_key(&price(102.0)));
+        assert!(!order_book.bids.contains_key(&price(100.0)));
+    }
+
+    #[test]
+    fn test_unbounded_book_keeps_deep_levels() {
+        // more levels than the configured depth of an incremental feed
+        let exchange = ExchangeConfig {
+            depth_limit: Some(2),
+            feed: Some(Feed::Incremental),
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::with_depth_limit(exchange.book_depth_limit());
+        for (p, q) in [(100.0, 1.0), (99.0, 2.0), (98.0, 3.0), (97.0, 4.0)] {
+            order_book.process_update(OrderBookUpdate {
+                price: price(p),
+                quantity: quantity(q),
+                side: Side::Buy,
+            });
+        }
+
+        // the venue deletes the top of the book, the deeper levels are still there
+        for p in [100.0, 99.0] {
+            order_book.process_update(OrderBookUpdate {
+                price: price(p),
+                quantity: quantity(0.0),
+                side: Side::Buy,
+            });
+        }
+        assert_eq!(
+            order_book.top_n(Side::Buy, 10),
+            vec![
+                Level {
+                    price: price(98.0),
+                    quantity: quantity(3.0)
+                },
+                Level {
+                    price: price(97.0),
+                    quantity: quantity(4.0)
+                },
+            ]
+        );
+        assert_eq!(order_book.best_bid, Some(price(98.0)));
+        // views are limited by their depth instead
+        assert_eq!(order_book.snapshot(Some(1)).bids.len(), 1);
+    }
+
+    fn delta(
+        sequence: u64,
+        prev_sequence: Option<u64>,
+        updates: Vec<OrderBookUpdate>,
+    ) -> OrderBookDelta {
+        OrderBookDelta {
+            updates,
+            sequence,
+            prev_sequence,
+        }
+    }
+
+    fn snapshot(sequence: u64) -> OrderBookSnapshot {
+        OrderBookSnapshot {
+            bids: vec![OrderBookUpdate {
+                price: price(100.0),
+                quantity: quantity(1.0),
+                side: Side::Buy,
+            }],
+            asks: vec![OrderBookUpdate {
+                price: price(102.0),
+                quantity: quantity(1.0),
+                side: Side::Sell,
+            }],
+            sequence: Some(sequence),
+        }
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let mut order_book = OrderBook::new(10);
+        order_book.apply_snapshot(snapshot(1));
+
+        let status = order_book.apply_delta(delta(
+            2,
+            Some(1),
+            vec![
+                OrderBookUpdate {
+                    price: price(101.0),
+                    quantity: quantity(3.0),
+                    side: Side::Buy,
+                },
+                OrderBookUpdate {
+                    price: price(102.0),
+                    quantity: quantity(0.0),
+                    side: Side::Sell,
+                },
+                OrderBookUpdate {
+                    price: price(103.0),
+                    quantity: quantity(2.0),
+                    side: Side::Sell,
+                },
+            ],
+        ));
+
+        assert_eq!(status, SequenceStatus::Applied);
+        assert_eq!(order_book.sequence, Some(2));
+        assert_eq!(order_book.bids.len(), 2);
+        assert_eq!(order_book.best_bid, Some(price(101.0)));
+        assert_eq!(order_book.best_ask, Some(price(103.0)));
+    }
+
+    #[test]
+    fn test_apply_delta_stale() {
+        let mut order_book = OrderBook::new(10);
+        order_book.apply_snapshot(snapshot(5));
+
+        let status = order_book.apply_delta(delta(4, None, vec![]));
+
+        assert_eq!(status, SequenceStatus::Stale);
+        assert_eq!(order_book.sequence, Some(5));
+    }
+
+    #[test]
+    fn test_apply_delta_gap() {
+        let mut order_book = OrderBook::new(10);
+        order_book.apply_snapshot(snapshot(1));
+
+        let status = order_book.apply_delta(delta(4, Some(3), vec![]));
+        assert_eq!(
+            status,
+            SequenceStatus::Gap {
+                expected: 1,
+                received: 3
+            }
+        );
+        // readers do not see the inconsistent levels
+        assert!(order_book.bids.is_empty() && order_book.asks.is_empty());
+        assert_eq!(order_book.best_bid, None);
+
+        // out of sync until the next snapshot
+        let status = order_book.apply_delta(delta(5, Some(4), vec![]));
+        assert_eq!(status, SequenceStatus::AwaitingSnapshot);
+
+        order_book.apply_snapshot(snapshot(5));
+        let status = order_book.apply_delta(delta(6, Some(5), vec![]));
+        assert_eq!(status, SequenceStatus::Applied);
+    }
+
+    #[test]
+    fn test_levels_best_first() {
+        let mut order_book = OrderBook::new(10);
+        order_book.apply_snapshot(OrderBookSnapshot {
+            bids: [(100.0, 1.0), (101.0, 2.0), (99.0, 3.0)]
+                .into_iter()
+                .map(|(p, q)| OrderBookUpdate {
+                    price: price(p),
+                    quantity: quantity(q),
+                    side: Side::Buy,
+                })
+                .collect(),
+            asks: [(103.0, 4.0), (102.0, 5.0)]
+                .into_iter()
+                .map(|(p, q)| OrderBookUpdate {
+                    price: price(p),
+                    quantity: quantity(q),
+                    side: Side::Sell,
+                })
+                .collect(),
+            sequence: Some(7),
+        });
+
+        let bids = order_book.top_n(Side::Buy, 2);
+        assert_eq!(
+            bids,
+            vec![
+                Level {
+                    price: price(101.0),
+                    quantity: quantity(2.0)
+                },
+                Level {
+                    price: price(100.0),
+                    quantity: quantity(1.0)
+                },
+            ]
+        );
+        assert_eq!(order_book.top_n(Side::Sell, 10)[0].price, price(102.0));
+
+        assert_eq!(
+            order_book.level_at(price(103.0)),
+            Some((Side::Sell, quantity(4.0)))
+        );
+        assert_eq!(order_book.level_at(price(50.0)), None);
+
+        let snapshot = order_book.snapshot(Some(1));
+        assert_eq!(snapshot.bids.len(), 1);
+        assert_eq!(snapshot.asks.len(), 1);
+        assert_eq!(snapshot.best_bid, Some(price(101.0)));
+        assert_eq!(snapshot.best_ask, Some(price(102.0)));
+        assert_eq!(snapshot.sequence, Some(7));
+        assert_eq!(order_book.snapshot(None).bids.len(), 3);
+
+        let stats = order_book.stats();
+        assert_eq!((stats.bid_depth, stats.ask_depth), (3, 2));
+        assert_eq!(stats.spread(), Some(price(1.0)));
+    }
+
+    #[test]
+    fn test_cross_detection_and_purge() {
+        let mut order_book = OrderBook::new(10);
+        for (p, side) in [
+            (99.0, Side::Buy),
+            (101.0, Side::Buy),
+            (100.0, Side::Sell),
+            (102.0, Side::Sell),
+        ] {
+            order_book.add_order(
+                Order {
+                    price: price(p),
+                    quantity: quantity(1.0),
+                },
+                side,
+            );
+        }
+        assert_eq!(order_book.cross_state(), CrossState::Crossed);
+
+        assert_eq!(order_book.purge_crossing(), 2);
+        assert_eq!(order_book.cross_state(), CrossState::Normal);
+        assert_eq!(order_book.best_bid, Some(price(99.0)));
+        assert_eq!(order_book.best_ask, Some(price(102.0)));
+
+        order_book.add_order(
+            Order {
+                price: price(102.0),
+                quantity: quantity(1.0),
+            },
+            Side::Buy,
+        );
+        assert_eq!(order_book.snapshot(None).cross_state(), CrossState::Locked);
     }
 }
@@ -0,0 +1,235 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::broadcast;
+
+use super::{
+    fixed_point::Quantity,
+    messages::{Side, Trade},
+};
+
+/// Trades kept by default, enough for an hour of a busy instrument.
+pub const DEFAULT_TRADES_CAPACITY: usize = 10_000;
+const STREAM_CAPACITY: usize = 1024;
+
+/// Rolling buffer of the recent public trades of an instrument, fed next to its order book.
+///
+/// Unlike the book changes, trades are not conflated: `subscribe` streams every trade and
+/// a receiver lagging more than the stream capacity misses the oldest ones
+/// (`RecvError::Lagged`), while the buffer keeps the last `capacity` trades.
+#[derive(Clone)]
+pub struct SharedTrades {
+    buffer: Arc<Mutex<VecDeque<Trade>>>,
+    capacity: usize,
+    stream: broadcast::Sender<Trade>,
+}
+
+impl SharedTrades {
+    pub fn new(capacity: usize) -> Self {
+        let (stream, _) = broadcast::channel(STREAM_CAPACITY);
+        SharedTrades {
+            buffer: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            stream,
+        }
+    }
+
+    /// Append the trades, oldest first, dropping the oldest ones beyond the capacity.
+    pub fn record(&self, trades: Vec<Trade>) {
+        let mut buffer = self.buffer.lock().unwrap();
+        for trade in trades {
+            if buffer.len() == self.capacity {
+                buffer.pop_front();
+            }
+            // no subscribers is fine
+            let _ = self.stream.send(trade.clone());
+            buffer.push_back(trade);
+        }
+    }
+
+    /// Stream of the trades recorded from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Trade> {
+        self.stream.subscribe()
+    }
+
+    /// Up to `n` latest trades, newest first.
+    pub fn recent(&self, n: usize) -> Vec<Trade> {
+        let buffer = self.buffer.lock().unwrap();
+        buffer.iter().rev().take(n).cloned().collect()
+    }
+
+    /// Buffered trades at or after the exchange time, oldest first.
+    pub fn since(&self, timestamp_us: u64) -> Vec<Trade> {
+        let buffer = self.buffer.lock().unwrap();
+        let start = buffer.partition_point(|trade| trade.timestamp_us < timestamp_us);
+        buffer.range(start..).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for SharedTrades {
+    fn default() -> Self {
+        Self::new(DEFAULT_TRADES_CAPACITY)
+    }
+}
+
+/// Buy and sell volume of the trades of a window, by the aggressor side.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Volume {
+    pub buy: Quantity,
+    pub sell: Quantity,
+}
+
+impl Volume {
+    pub fn total(&self) -> Quantity {
+        self.buy + self.sell
+    }
+
+    /// Buy volume per unit of sell volume, `None` without sells.
+    pub fn buy_sell_ratio(&self) -> Option<f64> {
+        (!self.sell.is_zero()).then(|| self.buy.to_f64() / self.sell.to_f64())
+    }
+
+    fn add(&mut self, trade: &Trade) {
+        match trade.aggressor {
+            Side::Buy => self.buy += trade.quantity,
+            Side::Sell => self.sell += trade.quantity,
+        }
+    }
+}
+
+/// Volume per second of exchange time over a horizon, for rolling totals of any window
+/// up to the horizon without keeping the trades.
+pub struct RollingVolume {
+    horizon_secs: u64,
+    /// (second since the Unix epoch, volume of the second), oldest first.
+    buckets: VecDeque<(u64, Volume)>,
+}
+
+impl RollingVolume {
+    pub fn new(horizon: Duration) -> Self {
+        RollingVolume {
+            horizon_secs: horizon.as_secs().max(1),
+            buckets: VecDeque::new(),
+        }
+    }
+
+    /// Count the trade in the second of its exchange time, seconds beyond the horizon are dropped.
+    pub fn record(&mut self, trade: &Trade) {
+        let second = trade.timestamp_us / 1_000_000;
+        // trades arrive in order, late ones of a batch are found from the back
+        let position = self
+            .buckets
+            .iter()
+            .rposition(|(bucket, _)| *bucket <= second);
+        match position {
+            Some(i) if self.buckets[i].0 == second => self.buckets[i].1.add(trade),
+            _ => {
+                let mut volume = Volume::default();
+                volume.add(trade);
+                let index = position.map_or(0, |i| i + 1);
+                self.buckets.insert(index, (second, volume));
+            }
+        }
+
+        let latest = self.buckets.back().map_or(second, |(bucket, _)| *bucket);
+        while self
+            .buckets
+            .front()
+            .is_some_and(|(bucket, _)| bucket + self.horizon_secs <= latest)
+        {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// Volume of the trades in the `window` up to `now_us`, to the second.
+    pub fn volume(&self, window: Duration, now_us: u64) -> Volume {
+        let from = now_us.saturating_sub(window.as_micros() as u64) / 1_000_000;
+        let mut volume = Volume::default();
+        for (_, bucket) in self.buckets.iter().filter(|(second, _)| *second >= from) {
+            volume.buy += bucket.buy;
+            volume.sell += bucket.sell;
+        }
+        volume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixed_point::Price;
+
+    fn trade_at(side: Side, quantity: f64, timestamp_us: u64) -> Trade {
+        Trade {
+            price: Price::from_f64(100.0),
+            quantity: Quantity::from_f64(quantity),
+            aggressor: side,
+            trade_id: timestamp_us.to_string(),
+            timestamp_us,
+        }
+    }
+
+    fn trade(id: u64) -> Trade {
+        Trade {
+            price: Price::from_f64(100.0 + id as f64),
+            quantity: Quantity::from_f64(1.0),
+            aggressor: Side::Buy,
+            trade_id: id.to_string(),
+            timestamp_us: id * 1_000,
+        }
+    }
+
+    #[test]
+    fn test_buffer_keeps_latest_trades() {
+        let trades = SharedTrades::new(3);
+        let mut stream = trades.subscribe();
+
+        trades.record((1..=4).map(trade).collect());
+
+        assert_eq!(trades.len(), 3);
+        let ids = |trades: Vec<Trade>| {
+            trades
+                .into_iter()
+                .map(|trade| trade.trade_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(trades.recent(2)), vec!["4", "3"]);
+        assert_eq!(ids(trades.since(3_000)), vec!["3", "4"]);
+        // the stream is not limited by the buffer capacity
+        assert_eq!(stream.try_recv().unwrap().trade_id, "1");
+    }
+
+    #[test]
+    fn test_rolling_volume() {
+        let second = 1_000_000;
+        let now = 10_000 * second;
+        let mut volume = RollingVolume::new(Duration::from_secs(3600));
+        volume.record(&trade_at(Side::Buy, 1.0, now - 7200 * second));
+        volume.record(&trade_at(Side::Buy, 2.0, now - 120 * second));
+        volume.record(&trade_at(Side::Sell, 1.0, now - 30 * second));
+        volume.record(&trade_at(Side::Buy, 0.5, now - 30 * second + 1));
+        // late trade of an earlier second
+        volume.record(&trade_at(Side::Sell, 3.0, now - 300 * second));
+
+        let minute = volume.volume(Duration::from_secs(60), now);
+        assert_eq!(minute.buy, Quantity::from_f64(0.5));
+        assert_eq!(minute.sell, Quantity::from_f64(1.0));
+        assert_eq!(minute.buy_sell_ratio(), Some(0.5));
+
+        let hour = volume.volume(Duration::from_secs(3600), now);
+        assert_eq!(hour.total(), Quantity::from_f64(6.5));
+        // beyond the horizon
+        assert_eq!(volume.buckets.len(), 3);
+        assert_eq!(Volume::default().buy_sell_ratio(), None);
+    }
+}
@@ -0,0 +1,75 @@
+use std::{io, time::Duration};
+
+use tokio_tungstenite::tungstenite;
+
+/// Errors of the crate, so embedding applications can tell a bad configuration
+/// from a venue which cannot be reached or a feed which broke.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// Missing or invalid configuration value.
+    #[error("{0}")]
+    Config(String),
+    /// The connection to the venue could not be opened.
+    #[error("Unable to connect to {url}: {source}")]
+    Connect {
+        url: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The channel of the instrument could not be subscribed to.
+    #[error("Unable to subscribe to {instrument}: {source}")]
+    Subscribe {
+        instrument: String,
+        #[source]
+        source: Box<Error>,
+    },
+    /// A frame or a recording is not valid JSON of the expected shape.
+    #[error("Unable to parse: {0}")]
+    Parse(#[from] serde_json::Error),
+    /// The venue does not behave as its protocol requires, e.g. closed the connection.
+    #[error("{0}")]
+    Protocol(String),
+    /// No message of the instrument for the age while the connection stayed open.
+    #[error("No messages for {instrument} in {age:?}")]
+    Stale { instrument: String, age: Duration },
+    /// Sending to or reading from the open connection failed.
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The provider is used before `connect`.
+    #[error("{0} is not connected")]
+    NotConnected(&'static str),
+    /// The instrument is not fed by the provider.
+    #[error("{instrument} is not added for {provider}")]
+    NotAdded {
+        provider: String,
+        instrument: String,
+    },
+    /// The provider task is not running anymore.
+    #[error("Provider task is stopped")]
+    Stopped,
+    /// The supervisor ran out of reconnect attempts, with the last connection failure.
+    #[error("Giving up after {attempts} reconnect attempts: {source}")]
+    GaveUp {
+        attempts: u32,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+// boxed, the error of tungstenite would make every result of the crate large
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Error::WebSocket(Box::new(e))
+    }
+}
+
+impl Error {
+    pub(crate) fn subscribe(instrument: &str, source: Error) -> Self {
+        Error::Subscribe {
+            instrument: instrument.to_string(),
+            source: Box::new(source),
+        }
+    }
+}
@@ -1,5 +1,7 @@
 pub mod cli;
 pub mod console;
 pub mod core;
+pub mod error;
 pub mod providers;
+pub mod server;
 pub mod utils;
@@ -1,36 +1,139 @@
+use l2_order_book::console::output::setup_stream_output;
 use l2_order_book::console::{listen_user_input, setup_console_output};
-use l2_order_book::core::SharedOrderBook;
-use l2_order_book::providers::subscribe_to_provider;
-use l2_order_book::utils::config::Config;
+use l2_order_book::providers::BookManager;
+use l2_order_book::server::{BookDirectory, HttpServer, MetricsServer, WsServer};
+use l2_order_book::utils::config::{Config, OutputFormat, Provider};
+use std::process::ExitCode;
+
+const DEFAULT_OUTPUT_LEVELS: usize = 5;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
     // config
-    let config = Config::read_config();
-    config.validate();
+    let config = match Config::read_config().and_then(|config| config.validate().map(|_| config)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
 
     // logger
     env_logger::init();
-    println!("Configuration: {:?}", config);
+    // stdout is reserved for the records of the streamed output
+    eprintln!("Configuration: {:?}", config);
+
+    // subscribe to the order books of all instruments, multiplexed over one connection
+    let provider = config.provider.name.clone().unwrap_or(Provider::None);
+    let instruments = config.exchange.instruments();
+    let mut manager = BookManager::from_config(&config);
+    let books = BookDirectory::new();
+    for instrument in &instruments {
+        match manager.add(&provider, instrument).await {
+            Ok(order_book) => books.insert(instrument, order_book),
+            Err(e) => {
+                let _ = manager.stop().await;
+                eprintln!("{}", e);
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
 
-    // create shared order book
-    let order_book = SharedOrderBook::initialise(config.exchange.depth_limit.unwrap());
+    // setup console for the first instrument, the servers serve all of them
+    let instrument = &instruments[0];
+    let order_book = manager
+        .get(&provider, instrument)
+        .expect("added instrument");
+    let headless = config.output.is_headless();
+    let output_cancellation_tx = match config.output.format.unwrap_or_default() {
+        OutputFormat::Ui => setup_console_output(order_book),
+        format => Some(setup_stream_output(
+            order_book,
+            format,
+            instrument.clone(),
+            config.output.levels.unwrap_or(DEFAULT_OUTPUT_LEVELS),
+        )),
+    };
 
-    // setup console and subscribe to provider events
-    let ui_cancellation_tx = setup_console_output(order_book.clone());
-    let subscribe_canclellation_tx = subscribe_to_provider(config, order_book);
-    let mut user_key_pressed_tx = listen_user_input();
+    // serve the books to downstream consumers
+    let server_config = config.server.clone();
+    let ws_server = match &server_config.ws_address {
+        Some(address) => match WsServer::bind(address, books.clone()).await {
+            Ok(server) => Some(server),
+            Err(e) => {
+                eprintln!("Unable to serve WebSocket clients on {}: {}", address, e);
+                None
+            }
+        },
+        None => None,
+    };
+    let feeds = manager
+        .health(&provider)
+        .map(|health| (provider.name(), health))
+        .into_iter()
+        .collect();
+    let http_server = match &server_config.http_address {
+        Some(address) => match HttpServer::bind(address, books.clone(), feeds).await {
+            Ok(server) => Some(server),
+            Err(e) => {
+                eprintln!("Unable to serve the HTTP API on {}: {}", address, e);
+                None
+            }
+        },
+        None => None,
+    };
+    let metrics_server = match &server_config.metrics_address {
+        Some(address) => match MetricsServer::bind(address, books.clone()).await {
+            Ok(server) => Some(server),
+            Err(e) => {
+                eprintln!("Unable to serve the metrics on {}: {}", address, e);
+                None
+            }
+        },
+        None => None,
+    };
 
-    // listen cancellation
-    user_key_pressed_tx.recv().await.unwrap();
+    // listen cancellation, without a terminal UI there are no key events,
+    // or stop when the provider gives up
+    let cancelled = async {
+        if headless {
+            tokio::signal::ctrl_c().await
+        } else {
+            let mut user_key_pressed_tx = listen_user_input();
+            user_key_pressed_tx.recv().await;
+            Ok(())
+        }
+    };
+    let failure = tokio::select! {
+        cancelled = cancelled => {
+            cancelled?;
+            None
+        }
+        stopped = manager.stopped() => stopped.err(),
+    };
 
     // clean up and cancel all tasks
-    if let Some(subscribe_canclellation_tx) = subscribe_canclellation_tx {
-        subscribe_canclellation_tx.send(()).await?;
+    if let Some(ws_server) = ws_server {
+        ws_server.stop();
+    }
+    if let Some(http_server) = http_server {
+        http_server.stop();
     }
-    if let Some(ui_cancellation_tx) = ui_cancellation_tx {
-        ui_cancellation_tx.send(()).await?;
+    if let Some(metrics_server) = metrics_server {
+        metrics_server.stop();
+    }
+    if failure.is_none() {
+        manager.stop().await?;
+    }
+    if let Some(output_cancellation_tx) = output_cancellation_tx {
+        output_cancellation_tx.send(()).await?;
     }
 
-    Ok(())
+    match failure {
+        Some(e) => {
+            eprintln!("{}", e);
+            Ok(ExitCode::FAILURE)
+        }
+        None => Ok(ExitCode::SUCCESS),
+    }
 }
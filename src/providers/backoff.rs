@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::utils::config::ReconnectConfig;
+
+const DEFAULT_INITIAL_DELAY_MS: u64 = 500;
+const DEFAULT_MAX_DELAY_MS: u64 = 30_000;
+const DEFAULT_MULTIPLIER: f64 = 2.0;
+const DEFAULT_JITTER: f64 = 0.2;
+
+/// Exponential backoff with jitter between reconnect attempts.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    jitter: f64,
+    max_attempts: Option<u32>,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(config: &ReconnectConfig) -> Self {
+        Backoff {
+            initial_delay: Duration::from_millis(
+                config.initial_delay_ms.unwrap_or(DEFAULT_INITIAL_DELAY_MS),
+            ),
+            max_delay: Duration::from_millis(config.max_delay_ms.unwrap_or(DEFAULT_MAX_DELAY_MS)),
+            multiplier: config.multiplier.unwrap_or(DEFAULT_MULTIPLIER).max(1.0),
+            jitter: config.jitter.unwrap_or(DEFAULT_JITTER).clamp(0.0, 1.0),
+            max_attempts: config.max_attempts,
+            attempt: 0,
+        }
+    }
+
+    /// Delay before the next attempt, `None` when all attempts are used.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| self.attempt >= max) {
+            return None;
+        }
+
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.powi(self.attempt as i32);
+        let delay = delay.min(self.max_delay.as_secs_f64());
+        let spread = if self.jitter > 0.0 {
+            rand::thread_rng().gen_range(-self.jitter..=self.jitter)
+        } else {
+            0.0
+        };
+        self.attempt += 1;
+
+        Some(Duration::from_secs_f64(delay * (1.0 + spread)))
+    }
+
+    /// Number of attempts since the last reset.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Start over after a healthy connection.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ReconnectConfig {
+        ReconnectConfig {
+            initial_delay_ms: Some(100),
+            max_delay_ms: Some(500),
+            multiplier: Some(2.0),
+            jitter: Some(0.0),
+            max_attempts: Some(5),
+        }
+    }
+
+    #[test]
+    fn test_exponential_delays() {
+        let mut backoff = Backoff::new(&config());
+        let delays: Vec<u128> = std::iter::from_fn(|| backoff.next_delay())
+            .map(|d| d.as_millis())
+            .collect();
+
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+        assert_eq!(backoff.attempt(), 5);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let mut backoff = Backoff::new(&ReconnectConfig {
+            jitter: Some(0.5),
+            max_attempts: None,
+            ..config()
+        });
+
+        for _ in 0..100 {
+            backoff.reset();
+            let delay = backoff.next_delay().unwrap().as_millis();
+            assert!((50..=150).contains(&delay), "delay {} out of bounds", delay);
+        }
+    }
+}
@@ -1,15 +1,33 @@
-use futures::{SinkExt, StreamExt};
-use serde::{Deserialize, Serialize};
-use std::error::Error;
-use tokio::sync::mpsc::Receiver;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
 
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use futures::{future::BoxFuture, stream, FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::tungstenite::protocol::Message;
 
-use crate::core::messages::{OrderBookUpdate, Side};
-use crate::{core::SharedOrderBook, utils::config::ExchangeConfig};
-use log::{debug, info};
+use super::{
+    ws::{self, Pinger, WsSink, WsSource},
+    MessageStream, Provider, Recorder,
+};
+use crate::core::fixed_point::{InstrumentSpec, InstrumentSpecs, ParseFixedError};
+use crate::core::messages::{
+    self, OrderBookDelta, OrderBookSnapshot, OrderBookUpdate, Side, Trade,
+};
+use crate::error::Error;
+use crate::utils::config::{normalize_instrument, ExchangeConfig, Feed};
+use crate::utils::metrics::{metrics, UNKNOWN_CHANNEL};
+use log::{debug, info, warn};
 
 const BITSTAMP_WS_URL: &str = "wss://ws.bitstamp.net";
+const PROVIDER_NAME: &str = "bitstamp";
+const ORDER_BOOK_CHANNEL: &str = "order_book";
+const DIFF_ORDER_BOOK_CHANNEL: &str = "diff_order_book";
+const LIVE_TRADES_CHANNEL: &str = "live_trades";
+
+/// Message tagged with the normalized instrument, the item of `MessageStream`.
+pub(super) type TaggedMessage = Result<(String, messages::Message, Instant), Error>;
+pub(super) type Reconcilers = Arc<std::sync::Mutex<HashMap<String, DiffReconciler>>>;
 
 #[derive(Serialize)]
 struct SubscribeMessage {
@@ -26,127 +44,565 @@ struct SubscribeData {
 struct RawOrderBookData {
     bids: Vec<[String; 2]>,
     asks: Vec<[String; 2]>,
+    #[serde(default)]
+    microtimestamp: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 struct RawOrderBook {
+    channel: String,
     data: RawOrderBookData,
 }
 
-#[derive(Debug)]
-struct OrderBook {
-    bids: Vec<OrderBookUpdate>,
-    asks: Vec<OrderBookUpdate>,
+#[derive(Deserialize, Debug)]
+struct RawTradeData {
+    id: u64,
+    price_str: String,
+    amount_str: String,
+    /// 0 for a buy, 1 for a sell taker.
+    #[serde(rename = "type")]
+    kind: u8,
+    microtimestamp: String,
 }
 
-impl From<RawOrderBookData> for OrderBook {
-    fn from(raw: RawOrderBookData) -> Self {
-        let bids = raw
-            .bids
-            .into_iter()
-            .map(|b| OrderBookUpdate {
-                price: b[0].parse().unwrap_or(0.0),
-                quantity: b[1].parse().unwrap_or(0.0),
-                side: Side::Buy,
-            })
-            .collect();
+#[derive(Deserialize, Debug)]
+struct RawTrade {
+    channel: String,
+    data: RawTradeData,
+}
 
-        let asks = raw
-            .asks
-            .into_iter()
-            .map(|a| OrderBookUpdate {
-                price: a[0].parse().unwrap_or(0.0),
-                quantity: a[1].parse().unwrap_or(0.0),
-                side: Side::Sell,
-            })
-            .collect();
+impl RawTradeData {
+    fn into_trade(self, spec: &InstrumentSpec) -> Option<Trade> {
+        let aggressor = match self.kind {
+            0 => Side::Buy,
+            1 => Side::Sell,
+            _ => return None,
+        };
+        Some(Trade {
+            price: spec.parse_price(&self.price_str).ok()?,
+            quantity: spec.parse_quantity(&self.amount_str).ok()?,
+            aggressor,
+            trade_id: self.id.to_string(),
+            timestamp_us: self.microtimestamp.parse().ok()?,
+        })
+    }
+}
+
+impl RawOrderBookData {
+    fn sequence(&self) -> Option<u64> {
+        self.microtimestamp.as_ref().and_then(|t| t.parse().ok())
+    }
+
+    fn into_updates(
+        self,
+        spec: &InstrumentSpec,
+    ) -> Result<(Vec<OrderBookUpdate>, Vec<OrderBookUpdate>), ParseFixedError> {
+        let levels = |levels: Vec<[String; 2]>, side: Side| {
+            levels
+                .iter()
+                .map(|[price, quantity]| {
+                    Ok(OrderBookUpdate {
+                        price: spec.parse_price(price)?,
+                        quantity: spec.parse_quantity(quantity)?,
+                        side,
+                    })
+                })
+                .collect::<Result<Vec<_>, ParseFixedError>>()
+        };
+
+        Ok((
+            levels(self.bids, Side::Buy)?,
+            levels(self.asks, Side::Sell)?,
+        ))
+    }
+
+    fn into_snapshot(self, spec: &InstrumentSpec) -> Result<OrderBookSnapshot, ParseFixedError> {
+        let sequence = self.sequence();
+        let (bids, asks) = self.into_updates(spec)?;
+
+        Ok(OrderBookSnapshot {
+            bids,
+            asks,
+            sequence,
+        })
+    }
+}
+
+/// Bitstamp provider.
+///
+/// Streams `order_book_{pair}` top 100 snapshots, or with the incremental feed every level
+/// change from `diff_order_book_{pair}`, seeded by the first `order_book_{pair}` snapshot.
+/// With trades enabled also streams `live_trades_{pair}`.
+pub struct Bitstamp {
+    config: ExchangeConfig,
+    write: Option<WsSink>,
+    read: Option<WsSource>,
+    reconcilers: Reconcilers,
+    recorder: Option<Recorder>,
+    pinger: Pinger,
+}
+
+impl Bitstamp {
+    pub fn new(config: ExchangeConfig) -> Self {
+        Bitstamp {
+            config,
+            write: None,
+            read: None,
+            reconcilers: Reconcilers::default(),
+            recorder: None,
+            pinger: Pinger::default(),
+        }
+    }
+
+    fn write(&self) -> Result<&WsSink, Error> {
+        self.write.as_ref().ok_or(Error::NotConnected("Bitstamp"))
+    }
+
+    /// Channels of the instrument, the diff channel goes first to not miss changes
+    /// published before the seeding snapshot.
+    fn channels(&self, instrument: &str) -> Vec<String> {
+        let mut channels = match self.config.feed.unwrap_or_default() {
+            Feed::Snapshot => vec![channel_name(ORDER_BOOK_CHANNEL, instrument)],
+            Feed::Incremental => vec![
+                channel_name(DIFF_ORDER_BOOK_CHANNEL, instrument),
+                channel_name(ORDER_BOOK_CHANNEL, instrument),
+            ],
+        };
+        if self.config.trades.unwrap_or_default() {
+            channels.push(channel_name(LIVE_TRADES_CHANNEL, instrument));
+        }
+        channels
+    }
+}
+
+impl Provider for Bitstamp {
+    fn connect(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        async move {
+            let url = self.config.url.as_deref().unwrap_or(BITSTAMP_WS_URL);
+            let (write, read) = ws::connect(url).await?;
+            self.write = Some(write);
+            self.read = Some(read);
+            self.reconcilers.lock().unwrap().clear();
+            self.pinger = Pinger::default();
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn subscribe<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        async move {
+            // every subscription is seeded by a fresh snapshot
+            if self.config.feed.unwrap_or_default() == Feed::Incremental {
+                self.reconcilers
+                    .lock()
+                    .unwrap()
+                    .insert(normalize_instrument(instrument), DiffReconciler::default());
+            }
+            for channel in self.channels(instrument) {
+                send_event(self.write()?, "bts:subscribe", &channel).await?;
+                info!("Subscribed to {}", channel);
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn unsubscribe<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        async move {
+            self.reconcilers
+                .lock()
+                .unwrap()
+                .remove(&normalize_instrument(instrument));
+            for channel in self.channels(instrument) {
+                send_event(self.write()?, "bts:unsubscribe", &channel).await?;
+                info!("Unsubscribed from {}", channel);
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn messages(&mut self) -> Result<MessageStream, Error> {
+        let read = self.read.take().ok_or(Error::NotConnected("Bitstamp"))?;
+
+        let specs = self.config.instrument_specs();
+        let recorder = self.recorder.clone();
+        let pinger = self.pinger.clone();
+        let messages = read.filter_map(move |message| {
+            let received = Instant::now();
+            if let (Some(recorder), Ok(Message::Text(text))) = (&recorder, &message) {
+                recorder.record(text);
+            }
+            if let Ok(Message::Pong(_)) = message {
+                pinger.pong();
+            }
+            let message = match message {
+                Ok(Message::Text(text)) => {
+                    parse(&text, &specs).map(|(channel, message)| Ok((channel, message, received)))
+                }
+                Ok(_) => None,
+                Err(e) => Some(Err(Error::from(e))),
+            };
+            async move { message }
+        });
+
+        match self.config.feed.unwrap_or_default() {
+            Feed::Snapshot => Ok(messages
+                .map(|message| {
+                    message.map(|(channel, message, received)| {
+                        (instrument(&channel), message, received)
+                    })
+                })
+                .boxed()),
+            Feed::Incremental => {
+                let write = self.write()?.clone();
+                let reconcilers = self.reconcilers.clone();
+
+                let messages = messages
+                    .map(move |message| match message {
+                        Ok((channel, message, received)) => {
+                            reconcile(&reconcilers, channel, message, received)
+                        }
+                        Err(e) => (vec![Err(e)], None),
+                    })
+                    .then(move |(messages, seeded_by): (Vec<_>, Option<String>)| {
+                        let write = write.clone();
+                        async move {
+                            // the snapshot channel is needed only to seed the book
+                            if let Some(channel) = seeded_by {
+                                if let Err(e) =
+                                    send_event(&write, "bts:unsubscribe", &channel).await
+                                {
+                                    warn!("Failed to unsubscribe from {}: {:?}", channel, e);
+                                }
+                            }
+                            stream::iter(messages)
+                        }
+                    })
+                    .flatten();
+
+                Ok(messages.boxed())
+            }
+        }
+    }
+
+    fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
 
-        OrderBook { bids, asks }
+    fn heartbeat(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        async move { self.pinger.ping(self.write()?).await }.boxed()
     }
 }
 
-/// Subscribe to the order book channel.
-pub async fn subscribe_to_order_book(
-    order_book: SharedOrderBook,
-    config: &ExchangeConfig,
-    mut stop_rx: Receiver<()>,
-) -> Result<(), Box<dyn Error>> {
-    // default provider (does not work without)
-    rustls::crypto::aws_lc_rs::default_provider()
-        .install_default()
-        .unwrap();
+/// Seeds the book from the first snapshot and replays the diffs buffered before it.
+///
+/// Diffs up to the snapshot `microtimestamp` are already part of the snapshot and are discarded.
+#[derive(Default)]
+pub(super) struct DiffReconciler {
+    seeded: bool,
+    buffer: Vec<OrderBookDelta>,
+}
+
+impl DiffReconciler {
+    fn is_seeded(&self) -> bool {
+        self.seeded
+    }
+
+    fn process(&mut self, message: messages::Message) -> Vec<messages::Message> {
+        match message {
+            messages::Message::Delta(delta) if !self.seeded => {
+                self.buffer.push(delta);
+                vec![]
+            }
+            messages::Message::Snapshot(_) if self.seeded => vec![],
+            messages::Message::Snapshot(snapshot) => {
+                self.seeded = true;
+                let sequence = snapshot.sequence.unwrap_or_default();
+                let buffered = self
+                    .buffer
+                    .drain(..)
+                    .filter(|delta| delta.sequence > sequence)
+                    .map(messages::Message::Delta);
+
+                debug!("Seeded order book from snapshot at {}", sequence);
+                std::iter::once(messages::Message::Snapshot(snapshot))
+                    .chain(buffered)
+                    .collect()
+            }
+            message => vec![message],
+        }
+    }
+}
 
-    // setuo ws stream
-    let (ws_stream, _) = connect_async(BITSTAMP_WS_URL)
-        .await
-        .expect("Failed to connect");
+/// Pass a message of the incremental feed through the reconciler of its instrument.
+///
+/// Returns the messages ready for the book, tagged with the normalized instrument, and
+/// the channel of the snapshot which seeded the book, if this message did.
+pub(super) fn reconcile(
+    reconcilers: &Reconcilers,
+    channel: String,
+    message: messages::Message,
+    received: Instant,
+) -> (Vec<TaggedMessage>, Option<String>) {
+    let instrument = instrument(&channel);
+    let mut reconcilers = reconcilers.lock().unwrap();
+    // late messages of an unsubscribed instrument
+    let Some(reconciler) = reconcilers.get_mut(&instrument) else {
+        return (vec![], None);
+    };
 
-    let (mut write, mut read) = ws_stream.split();
+    let seeded = reconciler.is_seeded();
+    let messages = reconciler.process(message);
+    let seeded_by = (!seeded && reconciler.is_seeded()).then_some(channel);
+    let messages = messages
+        .into_iter()
+        .map(|message| Ok((instrument.clone(), message, received)))
+        .collect();
+    (messages, seeded_by)
+}
 
-    // subscribe to channel
-    let instrument = config
-        .normalized_instrument()
-        .expect("Cannot normalize instrument");
-    let subscribe_message = SubscribeMessage {
-        event: "bts:subscribe".to_string(),
+async fn send_event(write: &WsSink, event: &str, channel: &str) -> Result<(), Error> {
+    let message = SubscribeMessage {
+        event: event.to_string(),
         data: SubscribeData {
-            channel: format!("order_book_{}", instrument),
+            channel: channel.to_string(),
         },
     };
+    ws::send_text(write, serde_json::to_string(&message)?).await
+}
 
-    let subscribe_message = serde_json::to_string(&subscribe_message)?;
-    write.send(Message::Text(subscribe_message)).await?;
-
-    // process messages
-    loop {
-        tokio::select! {
-            Some(message) = read.next() => {
-                match message {
-                    Ok(Message::Text(text)) => {
-                        if let Ok(raw_order_book) = serde_json::from_str::<RawOrderBook>(&text) {
-                            let order_book_data: RawOrderBookData = raw_order_book.data;
-
-                            // raw order book to OrderBook struct
-                            let order_book_update: OrderBook = order_book_data.into();
-
-                            // procced order book snapshot
-                            order_book
-                                .process_snapshot(
-                                    order_book_update.bids.clone(),
-                                    order_book_update.asks.clone(),
-                                )
-                                .await;
-
-                            debug!("Order Book: {:?}", order_book_update);
-                        } else {
-                            //error!("Failed to parse Order: {}", text);
-                            // SKIP other OK messages
-                        }
-                    }
-                    Ok(_) => {}
-                    Err(e) => {
-                        eprintln!("Error: {}", e);
-                        break;
-                    }
-                }
-            },
-            _ = stop_rx.recv() => {
-                info!("Stopping WebSocket stream");
-                println!("Requesting to close WebSocket stream for Bitstamp");
-                let unsubscribe_message = SubscribeMessage {
-                    event: "bts:unsubscribe".to_string(),
-                    data: SubscribeData {
-                        channel: format!("order_book_{}", instrument),
-                    },
-                };
-
-                let unsubscribe_message = serde_json::to_string(&unsubscribe_message)?;
-                write.send(Message::Text(unsubscribe_message)).await?;
-                info!("Unsubscribed from order_book_{}", instrument);
-                break;
-            },
+fn channel_name(channel: &str, instrument: &str) -> String {
+    format!("{}_{}", channel, normalize_instrument(instrument))
+}
+
+/// Normalized instrument of the channel, e.g. `btcusd` of `diff_order_book_btcusd`.
+pub(super) fn instrument(channel: &str) -> String {
+    [
+        DIFF_ORDER_BOOK_CHANNEL,
+        ORDER_BOOK_CHANNEL,
+        LIVE_TRADES_CHANNEL,
+    ]
+    .iter()
+    .find_map(|prefix| channel.strip_prefix(prefix)?.strip_prefix('_'))
+    .unwrap_or(channel)
+    .to_string()
+}
+
+/// Parse a data message into the channel name and the normalized message.
+pub(super) fn parse(text: &str, specs: &InstrumentSpecs) -> Option<(String, messages::Message)> {
+    let Ok(raw_order_book) = serde_json::from_str::<RawOrderBook>(text) else {
+        if let Ok(raw_trade) = serde_json::from_str::<RawTrade>(text) {
+            return parse_trade(raw_trade, text, specs);
+        }
+        // SKIP other messages, e.g. subscription confirmations
+        if serde_json::from_str::<serde::de::IgnoredAny>(text).is_err() {
+            metrics().parse_failure(PROVIDER_NAME, UNKNOWN_CHANNEL);
+            warn!("Skipped malformed frame: {}", text);
         }
+        return None;
+    };
+    let channel = raw_order_book.channel;
+    metrics().message_received(PROVIDER_NAME, &channel);
+    let spec = specs.get(&instrument(&channel));
+
+    let message = if channel.starts_with(DIFF_ORDER_BOOK_CHANNEL) {
+        let Some(sequence) = raw_order_book.data.sequence() else {
+            metrics().parse_failure(PROVIDER_NAME, &channel);
+            warn!("Skipped diff without microtimestamp: {}", text);
+            return None;
+        };
+        raw_order_book.data.into_updates(spec).map(|(bids, asks)| {
+            messages::Message::Delta(OrderBookDelta {
+                updates: bids.into_iter().chain(asks).collect(),
+                sequence,
+                prev_sequence: None,
+            })
+        })
+    } else {
+        raw_order_book
+            .data
+            .into_snapshot(spec)
+            .map(messages::Message::Snapshot)
+    };
+
+    // a level that cannot be parsed would corrupt the book, drop the whole message
+    let message = message
+        .map_err(|e| {
+            metrics().parse_failure(PROVIDER_NAME, &channel);
+            warn!("Skipped message with {}: {}", e, text)
+        })
+        .ok()?;
+    debug!("Order Book: {:?}", message);
+
+    Some((channel, message))
+}
+
+fn parse_trade(
+    raw_trade: RawTrade,
+    text: &str,
+    specs: &InstrumentSpecs,
+) -> Option<(String, messages::Message)> {
+    let channel = raw_trade.channel;
+    metrics().message_received(PROVIDER_NAME, &channel);
+    let spec = specs.get(&instrument(&channel));
+    let Some(trade) = raw_trade.data.into_trade(spec) else {
+        metrics().parse_failure(PROVIDER_NAME, &channel);
+        warn!("Skipped invalid trade: {}", text);
+        return None;
+    };
+    debug!("Trade: {:?}", trade);
+
+    Some((channel, messages::Message::Trades(vec![trade])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        fixed_point::{Price, Quantity},
+        messages::OrderBookUpdate,
+        order_book::{OrderBook, SequenceStatus},
+    };
+
+    fn diff(sequence: u64) -> messages::Message {
+        messages::Message::Delta(OrderBookDelta {
+            updates: vec![],
+            sequence,
+            prev_sequence: None,
+        })
+    }
+
+    #[test]
+    fn test_parse_diff() {
+        let text = r#"{"data":{"timestamp":"1721390000","microtimestamp":"1721390000123456","bids":[["64000.5","0.5"]],"asks":[["64010","0"]]},"channel":"diff_order_book_btcusd","event":"data"}"#;
+
+        let Some((channel, messages::Message::Delta(delta))) =
+            parse(text, &InstrumentSpecs::default())
+        else {
+            panic!("expected delta");
+        };
+        assert_eq!(channel, "diff_order_book_btcusd");
+        assert_eq!(instrument(&channel), "btcusd");
+        assert_eq!(delta.sequence, 1721390000123456);
+        assert_eq!(delta.updates.len(), 2);
+        assert_eq!(delta.updates[0].price.to_string(), "64000.5");
+        assert!(delta.updates[1].quantity.is_zero());
+    }
+
+    #[test]
+    fn test_parse_trade() {
+        let text = r#"{"data":{"id":344306391,"timestamp":"1721390000","amount":0.015,"amount_str":"0.01500000","price":64000.5,"price_str":"64000.5","type":1,"microtimestamp":"1721390000123456","buy_order_id":1,"sell_order_id":2},"channel":"live_trades_btcusd","event":"trade"}"#;
+
+        let Some((channel, messages::Message::Trades(trades))) =
+            parse(text, &InstrumentSpecs::default())
+        else {
+            panic!("expected trades");
+        };
+        assert_eq!(instrument(&channel), "btcusd");
+        assert_eq!(
+            trades,
+            vec![Trade {
+                price: "64000.5".parse().unwrap(),
+                quantity: "0.015".parse().unwrap(),
+                aggressor: Side::Sell,
+                trade_id: "344306391".to_string(),
+                timestamp_us: 1721390000123456,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_level() {
+        let text = r#"{"data":{"microtimestamp":"1721390000123456","bids":[["not a price","0.5"]],"asks":[]},"channel":"order_book_btcusd","event":"data"}"#;
+        assert!(parse(text, &InstrumentSpecs::default()).is_none());
     }
 
-    Ok(())
+    #[test]
+    fn test_parse_subscription_succeeded() {
+        let text =
+            r#"{"event":"bts:subscription_succeeded","channel":"order_book_btcusd","data":{}}"#;
+        assert!(parse(text, &InstrumentSpecs::default()).is_none());
+    }
+
+    #[test]
+    fn test_reconciler_discards_diffs_older_than_snapshot() {
+        let mut reconciler = DiffReconciler::default();
+        assert!(reconciler.process(diff(10)).is_empty());
+        assert!(reconciler.process(diff(20)).is_empty());
+        assert!(reconciler.process(diff(30)).is_empty());
+
+        let messages = reconciler.process(messages::Message::Snapshot(OrderBookSnapshot {
+            bids: vec![],
+            asks: vec![],
+            sequence: Some(20),
+        }));
+
+        assert!(reconciler.is_seeded());
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0], messages::Message::Snapshot(_)));
+        assert!(matches!(
+            messages[1],
+            messages::Message::Delta(OrderBookDelta { sequence: 30, .. })
+        ));
+
+        // further diffs pass through, snapshots are ignored
+        assert_eq!(reconciler.process(diff(40)).len(), 1);
+        let snapshot = messages::Message::Snapshot(OrderBookSnapshot {
+            bids: vec![],
+            asks: vec![],
+            sequence: Some(50),
+        });
+        assert!(reconciler.process(snapshot).is_empty());
+    }
+
+    #[test]
+    fn test_diff_book_keeps_levels_beyond_depth_limit() {
+        let level = |price: f64, quantity: f64| OrderBookUpdate {
+            price: Price::from_f64(price),
+            quantity: Quantity::from_f64(quantity),
+            side: Side::Buy,
+        };
+        let exchange = ExchangeConfig {
+            depth_limit: Some(2),
+            feed: Some(Feed::Incremental),
+            ..Default::default()
+        };
+        let mut order_book = OrderBook::with_depth_limit(exchange.book_depth_limit());
+        let mut reconciler = DiffReconciler::default();
+
+        // the snapshot seeds more levels than the depth limit
+        let seed = reconciler.process(messages::Message::Snapshot(OrderBookSnapshot {
+            bids: vec![
+                level(100.0, 1.0),
+                level(99.0, 2.0),
+                level(98.0, 3.0),
+                level(97.0, 4.0),
+            ],
+            asks: vec![],
+            sequence: Some(10),
+        }));
+        // then the best levels are deleted
+        let delete = reconciler.process(messages::Message::Delta(OrderBookDelta {
+            updates: vec![level(100.0, 0.0), level(99.0, 0.0)],
+            sequence: 20,
+            prev_sequence: None,
+        }));
+        for message in seed.into_iter().chain(delete) {
+            match message {
+                messages::Message::Snapshot(snapshot) => order_book.apply_snapshot(snapshot),
+                messages::Message::Delta(delta) => {
+                    assert_eq!(order_book.apply_delta(delta), SequenceStatus::Applied)
+                }
+                message => panic!("unexpected {:?}", message),
+            }
+        }
+
+        let bids: Vec<f64> = order_book
+            .levels(Side::Buy)
+            .map(|level| level.price.to_f64())
+            .collect();
+        assert_eq!(bids, vec![98.0, 97.0]);
+    }
 }
@@ -1,99 +1,517 @@
 use crate::{
-    core::{messages::OrderBookUpdate, messages::Side, SharedOrderBook},
-    utils::config::ExchangeConfig,
+    core::{
+        fixed_point::{InstrumentSpec, InstrumentSpecs, ParseFixedError, Quantity},
+        messages::{Message, OrderBookDelta, OrderBookSnapshot, OrderBookUpdate, Side, Trade},
+    },
+    error::Error,
+    utils::{
+        config::{normalize_instrument, ExchangeConfig, Feed},
+        metrics::{metrics, UNKNOWN_CHANNEL},
+    },
+};
+use deribit::models::{
+    subscription::{Delta, OrderBookDelta as DeribitBookDelta},
+    SubscriptionData, SubscriptionMessage, SubscriptionParams, WithChannel,
 };
-use anyhow::Error;
-use deribit::{
-    models::{
-        PublicSubscribeRequest, PublicUnsubscribeRequest, SubscriptionData, SubscriptionMessage,
-        SubscriptionParams, WithChannel,
+use futures::{future::BoxFuture, stream, FutureExt, StreamExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
     },
-    DeribitAPIClient, DeribitSubscriptionClient,
+    time::{Duration, Instant},
 };
-use futures::StreamExt;
-use log::{debug, error, info};
-use tokio::sync::mpsc::Receiver;
-
-pub async fn subscribe_to_order_book(
-    order_book: SharedOrderBook,
-    config: &ExchangeConfig,
-    mut stop_rx: Receiver<()>,
-) -> Result<(), Error> {
-    let (mut client, mut subscription) = create_client().await?;
-
-    subscribe_to_instrument(config, &mut client).await?;
-
-    loop {
-        tokio::select! {
-            _ = stop_rx.recv() => {
-                unsubscribe_from_instrument(config, &mut client).await?;
-                break;
-            },
-            Some(message) = subscription.next() => {
-                match message {
-                    Ok(subscription_meassage) => {
-                        if let Some((bids, asks)) = parse(subscription_meassage) {
-                            order_book.process_snapshot(bids, asks).await; // snapshot update, data equal to depth limit
-
-                            debug!(
-                                "Order book updated for instrument: {}",
-                                config.instrument.clone().unwrap()
-                            );
-                        }
-                    }
-                    Err(e) => {
-                        error!("Error in subscription message: {:?}", e);
-                        break;
-                    }
-                }
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time,
+};
+use tokio_tungstenite::tungstenite::protocol::Message as WsMessage;
+
+use super::{
+    ws::{self, Pinger, WsSink, WsSource},
+    MessageStream, Provider, Recorder, DEFAULT_HEARTBEAT_SECS,
+};
+
+const DERIBIT_WS_URL: &str = "wss://www.deribit.com/ws/api/v2";
+const DEFAULT_INTERVAL: &str = "100ms";
+const PROVIDER_NAME: &str = "deribit";
+/// Lowest interval accepted by `public/set_heartbeat`.
+const MIN_HEARTBEAT_SECS: u64 = 10;
+const TRADES_CHANNEL: &str = "trades";
+/// Time the venue has to answer a request.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Frames read ahead of the message stream, the reader waits for the stream beyond them.
+const FRAMES_CAPACITY: usize = 1024;
+
+/// Frame of the connection with the time it was read.
+type Frame = (Instant, Result<WsMessage, Error>);
+
+/// Requests of a connection awaiting their response.
+type Pending = Arc<Mutex<Requests>>;
+
+#[derive(Default)]
+struct Requests {
+    /// Waiters by request id, resolved with the error of the venue if any.
+    waiting: HashMap<u64, oneshot::Sender<Result<(), RpcError>>>,
+    /// The connection is gone, no response arrives anymore.
+    closed: bool,
+}
+
+impl Requests {
+    /// Fail every waiter at once rather than after `RESPONSE_TIMEOUT`.
+    fn close(&mut self) {
+        self.closed = true;
+        self.waiting.clear();
+    }
+}
+
+#[derive(Serialize)]
+struct RpcRequest<'a, P> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: P,
+}
+
+#[derive(Serialize)]
+struct ChannelsParams {
+    channels: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct HeartbeatParams {
+    interval: u64,
+}
+
+#[derive(Serialize)]
+struct EmptyParams {}
+
+#[derive(Deserialize, Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+/// Channel of a subscription notification, read apart from the data to count the frames.
+#[derive(Deserialize, Debug)]
+struct RawNotification {
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    params: Option<RawParams>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawParams {
+    #[serde(default)]
+    channel: Option<String>,
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+}
+
+impl RawNotification {
+    /// Heartbeat of `public/set_heartbeat` which has to be answered with `public/test`.
+    fn is_test_request(&self) -> bool {
+        self.method.as_deref() == Some("heartbeat")
+            && self
+                .params
+                .as_ref()
+                .and_then(|params| params.kind.as_deref())
+                == Some("test_request")
+    }
+
+    fn channel(self) -> Option<String> {
+        if self.method.as_deref() != Some("subscription") {
+            return None;
+        }
+        Some(
+            self.params
+                .and_then(|params| params.channel)
+                .unwrap_or_else(|| UNKNOWN_CHANNEL.to_string()),
+        )
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct RawTrades {
+    params: RawTradesParams,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawTradesParams {
+    data: Vec<RawTrade>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawTrade {
+    trade_id: String,
+    price: f64,
+    amount: f64,
+    direction: Direction,
+    /// Milliseconds since the Unix epoch.
+    timestamp: u64,
+    instrument_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    Buy,
+    Sell,
+}
+
+/// Response to a request, notifications carry no id.
+#[derive(Deserialize, Debug)]
+struct RpcResponse {
+    id: u64,
+    #[serde(default)]
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcErrorResponse {
+    #[serde(default)]
+    id: Option<u64>,
+    error: RpcError,
+}
+
+/// Deribit provider.
+///
+/// Streams grouped `book.{instrument}.5.{depth}.{interval}` snapshots, or with the incremental
+/// feed the non-grouped `book.{instrument}.{interval}` channel, which sends a full depth
+/// snapshot followed by changes chained by `prev_change_id`. With trades enabled also
+/// streams `trades.{instrument}.{interval}`.
+///
+/// Speaks JSON-RPC over its own connection, so the endpoint is configurable, and reuses
+/// the subscription models of the `deribit` crate. A reader task matches the responses
+/// to the requests by id, so a rejected subscription fails `subscribe` while the feed
+/// is not polled.
+pub struct Deribit {
+    config: ExchangeConfig,
+    write: Option<WsSink>,
+    frames: Option<mpsc::Receiver<Frame>>,
+    reader: Option<JoinHandle<()>>,
+    pending: Pending,
+    recorder: Option<Recorder>,
+    next_id: Arc<AtomicU64>,
+    pinger: Pinger,
+}
+
+impl Deribit {
+    pub fn new(config: ExchangeConfig) -> Self {
+        Deribit {
+            config,
+            write: None,
+            frames: None,
+            reader: None,
+            pending: Pending::default(),
+            recorder: None,
+            next_id: Arc::default(),
+            pinger: Pinger::default(),
+        }
+    }
+
+    fn write(&self) -> Result<&WsSink, Error> {
+        self.write.as_ref().ok_or(Error::NotConnected("Deribit"))
+    }
+
+    fn channels(&self, instrument: &str) -> Vec<String> {
+        let interval = self.config.interval.as_deref().unwrap_or(DEFAULT_INTERVAL);
+        let book = match self.config.feed.unwrap_or_default() {
+            Feed::Snapshot => {
+                let depth_limit = self.config.depth_limit.unwrap_or_default();
+                format!("book.{}.5.{}.{}", instrument, depth_limit, interval)
             }
+            Feed::Incremental => format!("book.{}.{}", instrument, interval),
+        };
+        let mut channels = vec![book];
+        if self.config.trades.unwrap_or_default() {
+            channels.push(format!("{}.{}.{}", TRADES_CHANNEL, instrument, interval));
         }
+        channels
     }
 
-    Ok(())
+    /// Send a JSON-RPC request and wait for its response, an error response fails the call.
+    async fn call(&self, method: &str, params: impl Serialize) -> Result<(), Error> {
+        let write = self.write()?;
+        let id = next_id(&self.next_id);
+        let (response_tx, response) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed {
+                return Err(closed_before_response(method));
+            }
+            pending.waiting.insert(id, response_tx);
+        }
+
+        if let Err(e) = send(write, id, method, params).await {
+            self.pending.lock().unwrap().waiting.remove(&id);
+            return Err(e);
+        }
+        match time::timeout(RESPONSE_TIMEOUT, response).await {
+            Ok(Ok(Ok(()))) => Ok(()),
+            Ok(Ok(Err(error))) => Err(Error::Protocol(format!(
+                "{} failed with {}: {}",
+                method, error.code, error.message
+            ))),
+            Ok(Err(_)) => Err(closed_before_response(method)),
+            Err(_) => {
+                self.pending.lock().unwrap().waiting.remove(&id);
+                Err(Error::Protocol(format!(
+                    "No response to {} in {:?}",
+                    method, RESPONSE_TIMEOUT
+                )))
+            }
+        }
+    }
 }
 
-async fn create_client() -> Result<(DeribitAPIClient, DeribitSubscriptionClient), Error> {
-    let drb = deribit::DeribitBuilder::default()
-        .build()
-        .expect("Cannot create deribit client");
+impl Drop for Deribit {
+    fn drop(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+        }
+    }
+}
 
-    drb.connect().await
+fn closed_before_response(method: &str) -> Error {
+    Error::Protocol(format!(
+        "Connection closed before the response to {}",
+        method
+    ))
 }
 
-async fn subscribe_to_instrument(
-    config: &ExchangeConfig,
-    client: &mut DeribitAPIClient,
-) -> Result<(), Error> {
-    let instrument = config.instrument.clone().unwrap();
-    let depth_limit = config.depth_limit.unwrap();
+fn next_id(next_id: &AtomicU64) -> u64 {
+    next_id.fetch_add(1, Ordering::Relaxed) + 1
+}
 
-    let subscription_channel = format!("book.{}.5.{}.100ms", instrument, depth_limit);
-    let req = PublicSubscribeRequest::new(&[subscription_channel]);
-    client.call(req).await?;
+/// Send a JSON-RPC request without waiting for the response.
+async fn send(write: &WsSink, id: u64, method: &str, params: impl Serialize) -> Result<(), Error> {
+    let request = RpcRequest {
+        jsonrpc: "2.0",
+        id,
+        method,
+        params,
+    };
+    ws::send_text(write, serde_json::to_string(&request)?).await
+}
 
-    Ok(())
+/// Read the frames of the connection, resolve the responses of the pending requests
+/// and forward everything else to the message stream.
+///
+/// A full stream holds the reader, responses arriving meanwhile wait like the data.
+/// Once the connection or the stream ends the pending requests fail.
+async fn read_frames(
+    read: WsSource,
+    frames: mpsc::Sender<Frame>,
+    pending: Pending,
+    recorder: Option<Recorder>,
+    pinger: Pinger,
+) {
+    forward_frames(read, frames, &pending, recorder, pinger).await;
+    pending.lock().unwrap().close();
 }
 
-async fn unsubscribe_from_instrument(
-    config: &ExchangeConfig,
-    client: &mut DeribitAPIClient,
-) -> Result<(), Error> {
-    info!("Stopping WebSocket stream");
-    println!("Requesting to close WebSocket stream for Deribit");
+async fn forward_frames(
+    mut read: WsSource,
+    frames: mpsc::Sender<Frame>,
+    pending: &Pending,
+    recorder: Option<Recorder>,
+    pinger: Pinger,
+) {
+    while let Some(message) = read.next().await {
+        let received = Instant::now();
+        match &message {
+            Ok(WsMessage::Text(text)) => {
+                if let Some(recorder) = &recorder {
+                    recorder.record(text);
+                }
+                if let Some(response) = parse_response(text) {
+                    let request = pending.lock().unwrap().waiting.remove(&response.id);
+                    if let Some(request) = request {
+                        let _ = request.send(response.error.map_or(Ok(()), Err));
+                        continue;
+                    }
+                }
+            }
+            Ok(WsMessage::Pong(_)) => pinger.pong(),
+            _ => {}
+        }
+        if frames
+            .send((received, message.map_err(Error::from)))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
 
-    let instrument = config.instrument.clone().unwrap();
-    let depth_limit = config.depth_limit.unwrap();
+fn parse_response(text: &str) -> Option<RpcResponse> {
+    // most frames are book data without a top level id, skip parsing them twice
+    if !text.contains("\"id\"") {
+        return None;
+    }
+    serde_json::from_str(text).ok()
+}
 
-    let subscription_channel = format!("book.{}.5.{}.100ms", instrument, depth_limit);
-    let req = PublicUnsubscribeRequest::new(&[subscription_channel.clone()]);
-    client.call(req).await?;
+impl Provider for Deribit {
+    fn connect(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        async move {
+            let url = self.config.url.as_deref().unwrap_or(DERIBIT_WS_URL);
+            let (write, read) = ws::connect(url).await?;
+            if let Some(reader) = self.reader.take() {
+                reader.abort();
+            }
+            let (frames_tx, frames) = mpsc::channel(FRAMES_CAPACITY);
+            self.write = Some(write);
+            self.frames = Some(frames);
+            self.pending = Pending::default();
+            self.pinger = Pinger::default();
+            self.reader = Some(tokio::spawn(read_frames(
+                read,
+                frames_tx,
+                self.pending.clone(),
+                self.recorder.clone(),
+                self.pinger.clone(),
+            )));
 
-    info!("Unsubscribed from {}", subscription_channel);
-    Ok(())
+            // the venue closes the connection when a `test_request` is not answered in time
+            let interval = self
+                .config
+                .heartbeat_secs
+                .unwrap_or(DEFAULT_HEARTBEAT_SECS)
+                .max(MIN_HEARTBEAT_SECS);
+            self.call("public/set_heartbeat", HeartbeatParams { interval })
+                .await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn subscribe<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        async move {
+            let channels = self.channels(instrument);
+            info!("Subscribing to {:?}", channels);
+            self.call("public/subscribe", ChannelsParams { channels })
+                .await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn unsubscribe<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        async move {
+            let channels = self.channels(instrument);
+            info!("Unsubscribing from {} ({:?})", instrument, channels);
+            self.call("public/unsubscribe", ChannelsParams { channels })
+                .await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn messages(&mut self) -> Result<MessageStream, Error> {
+        let frames = self.frames.take().ok_or(Error::NotConnected("Deribit"))?;
+
+        let specs = Arc::new(self.config.instrument_specs());
+        let write = self.write()?.clone();
+        let ids = self.next_id.clone();
+        let frames = stream::unfold(frames, |mut frames| async move {
+            frames.recv().await.map(|message| (message, frames))
+        });
+        let stream = frames.filter_map(move |(received, message)| {
+            let (write, ids, specs) = (write.clone(), ids.clone(), specs.clone());
+            async move {
+                match message {
+                    Ok(WsMessage::Text(text)) if is_test_request(&text) => {
+                        // answered from the feed, the response is not awaited
+                        debug!("Answering heartbeat test request");
+                        send(&write, next_id(&ids), "public/test", EmptyParams {})
+                            .await
+                            .err()
+                            .map(Err)
+                    }
+                    Ok(WsMessage::Text(text)) => parse_frame(&text, &specs)
+                        .map(|(instrument, message)| Ok((instrument, message, received))),
+                    Ok(_) => None,
+                    Err(e) => Some(Err(e)),
+                }
+            }
+        });
+
+        Ok(stream.boxed())
+    }
+
+    fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    fn heartbeat(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        async move { self.pinger.ping(self.write()?).await }.boxed()
+    }
+}
+
+fn is_test_request(text: &str) -> bool {
+    // most frames are book data, skip parsing them twice
+    text.contains("test_request")
+        && serde_json::from_str::<RawNotification>(text)
+            .is_ok_and(|notification| notification.is_test_request())
+}
+
+/// Parse a text frame, skipping responses to the requests and logging the errors.
+pub(super) fn parse_frame(text: &str, specs: &InstrumentSpecs) -> Option<(String, Message)> {
+    let Ok(notification) = serde_json::from_str::<RawNotification>(text) else {
+        metrics().parse_failure(PROVIDER_NAME, UNKNOWN_CHANNEL);
+        warn!("Skipped malformed frame: {}", text);
+        return None;
+    };
+    if let Some(channel) = notification.channel() {
+        metrics().message_received(PROVIDER_NAME, &channel);
+        if channel.starts_with(TRADES_CHANNEL) {
+            return parse_trades(text, &channel, specs);
+        }
+        return match serde_json::from_str::<SubscriptionMessage>(text) {
+            Ok(message) => parse(message, specs)
+                // a level that cannot be parsed would corrupt the book, drop the whole message
+                .map_err(|e| {
+                    metrics().parse_failure(PROVIDER_NAME, &channel);
+                    warn!("Skipped message with {}: {}", e, text)
+                })
+                .ok()
+                .flatten(),
+            Err(e) => {
+                metrics().parse_failure(PROVIDER_NAME, &channel);
+                warn!("Skipped message with {}: {}", e, text);
+                None
+            }
+        };
+    }
+    match serde_json::from_str::<RpcErrorResponse>(text) {
+        Ok(RpcErrorResponse { id, error }) => warn!(
+            "Request {:?} failed with {}: {}",
+            id, error.code, error.message
+        ),
+        Err(_) => debug!("Skipped frame: {}", text),
+    }
+    None
 }
 
-fn parse(msg: SubscriptionMessage) -> Option<(Vec<OrderBookUpdate>, Vec<OrderBookUpdate>)> {
+/// Parse a subscription message into the normalized instrument and the normalized message.
+///
+/// Fails when a level is off the grid of the instrument.
+fn parse(
+    msg: SubscriptionMessage,
+    specs: &InstrumentSpecs,
+) -> Result<Option<(String, Message)>, ParseFixedError> {
     match msg {
         SubscriptionMessage {
             params:
@@ -103,27 +521,267 @@ fn parse(msg: SubscriptionMessage) -> Option<(Vec<OrderBookUpdate>, Vec<OrderBoo
                 })),
             ..
         } => {
-            let asks: Vec<OrderBookUpdate> = data
+            let spec = specs.get(&normalize_instrument(&data.instrument_name));
+            let asks = data
                 .asks
                 .into_iter()
-                .map(|(price, quantity)| OrderBookUpdate {
-                    price,
-                    quantity,
-                    side: Side::Sell,
+                .map(|(price, quantity)| {
+                    Ok(OrderBookUpdate {
+                        price: spec.price(price)?,
+                        quantity: spec.quantity(quantity)?,
+                        side: Side::Sell,
+                    })
                 })
-                .collect();
-            let bids: Vec<OrderBookUpdate> = data
+                .collect::<Result<Vec<_>, ParseFixedError>>()?;
+            let bids = data
                 .bids
                 .into_iter()
-                .map(|(price, quantity)| OrderBookUpdate {
-                    price,
-                    quantity,
-                    side: Side::Buy,
+                .map(|(price, quantity)| {
+                    Ok(OrderBookUpdate {
+                        price: spec.price(price)?,
+                        quantity: spec.quantity(quantity)?,
+                        side: Side::Buy,
+                    })
                 })
-                .collect();
+                .collect::<Result<Vec<_>, ParseFixedError>>()?;
+
+            debug!(
+                "Order book updated for instrument: {}",
+                data.instrument_name
+            );
+
+            // snapshot update, data equal to depth limit
+            let message = Message::Snapshot(OrderBookSnapshot {
+                bids,
+                asks,
+                sequence: Some(data.change_id as u64),
+            });
+            Ok(Some((normalize_instrument(&data.instrument_name), message)))
+        }
+        SubscriptionMessage {
+            params:
+                SubscriptionParams::Subscription(SubscriptionData::Book(WithChannel { data, .. })),
+            ..
+        } => {
+            let spec = specs.get(&normalize_instrument(&data.instrument_name));
+            let bids = data
+                .bids
+                .into_iter()
+                .map(|delta| book_update(delta, Side::Buy, spec))
+                .collect::<Result<Vec<_>, ParseFixedError>>()?;
+            let asks = data
+                .asks
+                .into_iter()
+                .map(|delta| book_update(delta, Side::Sell, spec))
+                .collect::<Result<Vec<_>, ParseFixedError>>()?;
+
+            debug!(
+                "Order book changed for instrument: {}, change id: {}",
+                data.instrument_name, data.change_id
+            );
+
+            let message = match data.prev_change_id {
+                // the first message after subscription is a full snapshot
+                None => Message::Snapshot(OrderBookSnapshot {
+                    bids,
+                    asks,
+                    sequence: Some(data.change_id as u64),
+                }),
+                Some(prev_change_id) => Message::Delta(OrderBookDelta {
+                    updates: bids.into_iter().chain(asks).collect(),
+                    sequence: data.change_id as u64,
+                    prev_sequence: Some(prev_change_id as u64),
+                }),
+            };
+            Ok(Some((normalize_instrument(&data.instrument_name), message)))
+        }
+        _ => Ok(None), // Ignore other types of messages
+    }
+}
 
-            Some((bids, asks))
+/// Parse a `trades` notification, a batch of the trades of the interval.
+fn parse_trades(text: &str, channel: &str, specs: &InstrumentSpecs) -> Option<(String, Message)> {
+    let raw_trades = match serde_json::from_str::<RawTrades>(text) {
+        Ok(raw_trades) => raw_trades.params.data,
+        Err(e) => {
+            metrics().parse_failure(PROVIDER_NAME, channel);
+            warn!("Skipped trades with {}: {}", e, text);
+            return None;
         }
-        _ => None, // Ignore other types of messages
+    };
+    // the channel is of one instrument
+    let instrument = normalize_instrument(&raw_trades.first()?.instrument_name);
+    let spec = specs.get(&instrument);
+    let trades = raw_trades
+        .into_iter()
+        .map(|trade| {
+            Ok(Trade {
+                price: spec.price(trade.price)?,
+                quantity: spec.quantity(trade.amount)?,
+                aggressor: match trade.direction {
+                    Direction::Buy => Side::Buy,
+                    Direction::Sell => Side::Sell,
+                },
+                trade_id: trade.trade_id,
+                timestamp_us: trade.timestamp * 1_000,
+            })
+        })
+        .collect::<Result<Vec<_>, ParseFixedError>>();
+    let trades = match trades {
+        Ok(trades) => trades,
+        Err(e) => {
+            metrics().parse_failure(PROVIDER_NAME, channel);
+            warn!("Skipped trades with {}: {}", e, text);
+            return None;
+        }
+    };
+    debug!("Trades of {}: {:?}", instrument, trades);
+
+    Some((instrument, Message::Trades(trades)))
+}
+
+fn book_update(
+    DeribitBookDelta(delta, price, amount): DeribitBookDelta,
+    side: Side,
+    spec: &InstrumentSpec,
+) -> Result<OrderBookUpdate, ParseFixedError> {
+    let quantity = match delta {
+        Delta::New | Delta::Change => spec.quantity(amount)?,
+        Delta::Delete => Quantity::ZERO,
+    };
+
+    Ok(OrderBookUpdate {
+        price: spec.price(price)?,
+        quantity,
+        side,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(tick_size: &str, lot_size: &str) -> InstrumentSpec {
+        InstrumentSpec::new(tick_size.parse().unwrap(), lot_size.parse().unwrap())
+    }
+
+    /// BTC-PERPETUAL grid for every instrument, ETH-PERPETUAL on a finer one.
+    fn specs() -> InstrumentSpecs {
+        let mut specs = InstrumentSpecs::new(spec("0.5", "10"));
+        specs.insert("ethperpetual", spec("0.05", "1"));
+        specs
+    }
+
+    fn message(text: &str) -> SubscriptionMessage {
+        serde_json::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_book_snapshot() {
+        let msg = message(
+            r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.100ms","data":{"type":"snapshot","timestamp":1554373962454,"instrument_name":"BTC-PERPETUAL","change_id":297217,"bids":[["new",5042.5,30.0],["new",5041.5,20.0]],"asks":[["new",5043.0,40.0]]}}}"#,
+        );
+
+        let Ok(Some((instrument, Message::Snapshot(snapshot)))) = parse(msg, &specs()) else {
+            panic!("expected snapshot");
+        };
+        assert_eq!(instrument, "btcperpetual");
+        assert_eq!(snapshot.bids.len(), 2);
+        assert_eq!(snapshot.asks.len(), 1);
+        assert_eq!(snapshot.bids[0].price.to_string(), "5042.5");
+        assert_eq!(snapshot.sequence, Some(297217));
+    }
+
+    #[test]
+    fn test_parse_off_grid_level() {
+        // 5042.34 is not on the 0.5 tick, rounding it would overwrite the 5042.5 level
+        let frame = r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.100ms","data":{"type":"change","timestamp":1554373911330,"prev_change_id":297217,"instrument_name":"BTC-PERPETUAL","change_id":297218,"bids":[["new",5042.34,30.0]],"asks":[]}}}"#;
+        assert!(parse(message(frame), &specs()).is_err());
+        assert!(parse_frame(frame, &specs()).is_none());
+
+        // a quantity below the lot would otherwise become a delete
+        let frame = frame.replace("5042.34,30.0", "5042.5,4.0");
+        assert!(parse_frame(&frame, &specs()).is_none());
+        let frame = frame.replace("5042.5,4.0", "5042.5,40.0");
+        assert!(parse_frame(&frame, &specs()).is_some());
+    }
+
+    #[test]
+    fn test_parse_instruments_on_own_grids() {
+        let frame = |instrument: &str, price: f64| {
+            format!(
+                r#"{{"jsonrpc":"2.0","method":"subscription","params":{{"channel":"book.{0}.100ms","data":{{"type":"change","timestamp":1554373911330,"prev_change_id":1,"instrument_name":"{0}","change_id":2,"bids":[["new",{1},1.0]],"asks":[]}}}}}}"#,
+                instrument, price
+            )
+        };
+
+        // multiplexed on one connection, every message is checked against its instrument
+        let Some((instrument, Message::Delta(delta))) =
+            parse_frame(&frame("ETH-PERPETUAL", 3000.05), &specs())
+        else {
+            panic!("expected delta");
+        };
+        assert_eq!(instrument, "ethperpetual");
+        assert_eq!(delta.updates[0].price.to_string(), "3000.05");
+        assert!(parse_frame(&frame("ETH-PERPETUAL", 3000.01), &specs()).is_none());
+
+        // the finer grid does not loosen the check of BTC-PERPETUAL, nor its lot
+        assert!(parse_frame(&frame("BTC-PERPETUAL", 64000.05), &specs()).is_none());
+        assert!(parse_frame(&frame("BTC-PERPETUAL", 64000.5), &specs()).is_none());
+        let frame = frame("BTC-PERPETUAL", 64000.5).replace("1.0]", "10.0]");
+        assert!(parse_frame(&frame, &specs()).is_some());
+    }
+
+    #[test]
+    fn test_parse_frame_skips_responses() {
+        let response = r#"{"jsonrpc":"2.0","id":1,"result":["book.BTC-PERPETUAL.100ms"]}"#;
+        assert!(parse_frame(response, &specs()).is_none());
+        let error = r#"{"jsonrpc":"2.0","id":2,"error":{"code":11050,"message":"bad_request"}}"#;
+        assert!(parse_frame(error, &specs()).is_none());
+    }
+
+    #[test]
+    fn test_parse_trades() {
+        let text = r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"trades.BTC-PERPETUAL.100ms","data":[{"trade_seq":30289432,"trade_id":"48079254","timestamp":1590484156350,"tick_direction":0,"price":8950.0,"mark_price":8948.9,"instrument_name":"BTC-PERPETUAL","index_price":8955.88,"direction":"sell","amount":10.0},{"trade_seq":30289433,"trade_id":"48079255","timestamp":1590484156350,"tick_direction":1,"price":8950.5,"mark_price":8948.9,"instrument_name":"BTC-PERPETUAL","index_price":8955.88,"direction":"buy","amount":20.0}]}}"#;
+
+        let Some((instrument, Message::Trades(trades))) = parse_frame(text, &specs()) else {
+            panic!("expected trades");
+        };
+        assert_eq!(instrument, "btcperpetual");
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].aggressor, Side::Sell);
+        assert_eq!(trades[0].trade_id, "48079254");
+        assert_eq!(trades[0].timestamp_us, 1590484156350000);
+        assert_eq!(trades[1].aggressor, Side::Buy);
+        assert_eq!(trades[1].price.to_string(), "8950.5");
+    }
+
+    #[test]
+    fn test_is_test_request() {
+        assert!(is_test_request(
+            r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"test_request"}}"#
+        ));
+        assert!(!is_test_request(
+            r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"heartbeat"}}"#
+        ));
+        assert!(!is_test_request(
+            r#"{"jsonrpc":"2.0","id":3,"result":"test_request"}"#
+        ));
+    }
+
+    #[test]
+    fn test_parse_book_change() {
+        let msg = message(
+            r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.100ms","data":{"type":"change","timestamp":1554373911330,"prev_change_id":297217,"instrument_name":"BTC-PERPETUAL","change_id":297218,"bids":[["delete",5041.5,0.0]],"asks":[["change",5043.0,10.0]]}}}"#,
+        );
+
+        let Ok(Some((_, Message::Delta(delta)))) = parse(msg, &specs()) else {
+            panic!("expected delta");
+        };
+        assert_eq!(delta.sequence, 297218);
+        assert_eq!(delta.prev_sequence, Some(297217));
+        assert_eq!(delta.updates.len(), 2);
+        assert!(delta.updates[0].quantity.is_zero());
+        assert_eq!(delta.updates[1].quantity.to_string(), "10");
     }
 }
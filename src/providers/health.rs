@@ -0,0 +1,127 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::ProviderEvent;
+use crate::utils::metrics::metrics;
+
+/// Connection state of a provider as seen by the supervisor.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct HealthStatus {
+    pub connected: bool,
+    /// Time since the last message of the provider, in milliseconds.
+    pub last_message_age_ms: Option<u64>,
+    /// Reconnect attempts since the start.
+    pub reconnects: u64,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    connected: bool,
+    last_message: Option<Instant>,
+    reconnects: u64,
+}
+
+/// Live health of a provider feed, cheap to clone and read from other tasks.
+#[derive(Debug, Clone, Default)]
+pub struct FeedHealth {
+    state: Arc<Mutex<HealthState>>,
+}
+
+impl FeedHealth {
+    pub fn status(&self) -> HealthStatus {
+        let state = self.state.lock().unwrap();
+        HealthStatus {
+            connected: state.connected,
+            last_message_age_ms: state
+                .last_message
+                .map(|received| received.elapsed().as_millis() as u64),
+            reconnects: state.reconnects,
+        }
+    }
+
+    fn message(&self) {
+        self.state.lock().unwrap().last_message = Some(Instant::now());
+    }
+
+    fn event(&self, event: &ProviderEvent) {
+        let mut state = self.state.lock().unwrap();
+        match event {
+            ProviderEvent::Connected => state.connected = true,
+            ProviderEvent::Disconnected { .. } => state.connected = false,
+            ProviderEvent::Reconnecting { .. } => state.reconnects += 1,
+            ProviderEvent::Crossed { .. } | ProviderEvent::Stale { .. } => {}
+        }
+    }
+}
+
+/// Sink of the supervisor observations: events for subscribers, the feed health and the metrics.
+#[derive(Clone)]
+pub(super) struct Monitor {
+    provider: String,
+    pub(super) events: broadcast::Sender<ProviderEvent>,
+    pub(super) health: FeedHealth,
+}
+
+impl Monitor {
+    pub(super) fn new(provider: &str, capacity: usize) -> Self {
+        let (events, _) = broadcast::channel(capacity);
+        Monitor {
+            provider: provider.to_string(),
+            events,
+            health: FeedHealth::default(),
+        }
+    }
+
+    pub(super) fn emit(&self, event: ProviderEvent) {
+        self.health.event(&event);
+        if let ProviderEvent::Reconnecting { .. } = event {
+            metrics().reconnect(&self.provider);
+        }
+        // no subscribers is fine
+        let _ = self.events.send(event);
+    }
+
+    /// A message left the provider stream.
+    pub(super) fn message(&self) {
+        self.health.message();
+    }
+
+    /// The message whose frame was read from the connection at `received` is applied to the book.
+    pub(super) fn applied(&self, received: Instant) {
+        metrics().applied(&self.provider, received.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_health_follows_events() {
+        let monitor = Monitor::new("bitstamp", 4);
+        assert_eq!(monitor.health.status(), HealthStatus::default());
+
+        monitor.emit(ProviderEvent::Connected);
+        monitor.message();
+        let status = monitor.health.status();
+        assert!(status.connected);
+        assert!(status.last_message_age_ms.is_some());
+
+        monitor.emit(ProviderEvent::Disconnected {
+            reason: "closed".to_string(),
+        });
+        monitor.emit(ProviderEvent::Reconnecting {
+            attempt: 1,
+            delay: Duration::from_millis(10),
+        });
+        let status = monitor.health.status();
+        assert!(!status.connected);
+        assert_eq!(status.reconnects, 1);
+    }
+}
@@ -0,0 +1,529 @@
+use std::{collections::HashMap, sync::Arc};
+
+use futures::future;
+use log::warn;
+use tokio::sync::broadcast;
+
+use crate::{
+    core::SharedOrderBook,
+    error::Error,
+    utils::config::{
+        normalize_instrument, Config, ExchangeConfig, Provider as ProviderName, ReconnectConfig,
+        RecorderConfig,
+    },
+};
+
+use super::{
+    registry_with_replay, spawn, Backoff, FeedHealth, FeedOptions, ProviderEvent, ProviderHandle,
+    ProviderRegistry, Recorder, Subscription,
+};
+
+/// Order books of many instruments across providers, keyed by (provider, instrument).
+///
+/// Every provider runs a single connection, instruments are multiplexed over it
+/// and can be added or removed at runtime.
+pub struct BookManager {
+    registry: ProviderRegistry,
+    exchange: ExchangeConfig,
+    reconnect: ReconnectConfig,
+    recorder: RecorderConfig,
+    providers: HashMap<String, ProviderHandle>,
+    books: HashMap<(String, String), Subscription>,
+}
+
+impl BookManager {
+    /// Manager of the built-in providers. The exchange configuration is shared by
+    /// all books, e.g. the depth limit and the feed.
+    pub fn new(exchange: ExchangeConfig, reconnect: ReconnectConfig) -> Self {
+        Self::with_registry(ProviderRegistry::new(), exchange, reconnect)
+    }
+
+    /// Manager resolving the providers from the given registry.
+    pub fn with_registry(
+        registry: ProviderRegistry,
+        exchange: ExchangeConfig,
+        reconnect: ReconnectConfig,
+    ) -> Self {
+        BookManager {
+            registry,
+            exchange,
+            reconnect,
+            recorder: RecorderConfig::default(),
+            providers: HashMap::new(),
+            books: HashMap::new(),
+        }
+    }
+
+    /// Manager of the application configuration: the built-in providers and the replay,
+    /// recording the frames when enabled.
+    pub fn from_config(config: &Config) -> Self {
+        let mut manager = Self::with_registry(
+            registry_with_replay(config),
+            config.exchange.clone(),
+            config.reconnect.clone(),
+        );
+        manager.record(config.recorder.clone());
+        manager
+    }
+
+    /// Record the raw frames of the providers connected from now on.
+    pub fn record(&mut self, recorder: RecorderConfig) {
+        self.recorder = recorder;
+    }
+
+    /// Start feeding the order book of the instrument, connecting to the provider if needed.
+    ///
+    /// Returns the existing book when the instrument is already added. A provider which
+    /// stopped, e.g. gave up reconnecting, is started again and feeds all its books.
+    pub async fn add(
+        &mut self,
+        provider: &ProviderName,
+        instrument: &str,
+    ) -> Result<SharedOrderBook, Error> {
+        let key = (provider.name(), normalize_instrument(instrument));
+        if let Some(subscription) = self.books.get(&key) {
+            return Ok(subscription.order_book.clone());
+        }
+
+        let subscription = Subscription {
+            instrument: instrument.to_string(),
+            order_book: SharedOrderBook::with_depth_limit(self.exchange.book_depth_limit()),
+        };
+        let running = self
+            .providers
+            .get(&key.0)
+            .filter(|handle| !handle.is_stopped());
+        match running {
+            Some(handle) => {
+                handle
+                    .add(instrument, subscription.order_book.clone())
+                    .await?
+            }
+            None => {
+                // the exchange configuration holds the grids of every instrument
+                let mut venue = self.registry.create(provider, &self.exchange)?;
+                if self.recorder.is_enabled() {
+                    venue.set_recorder(Recorder::new(&self.recorder, &key.0)?);
+                }
+                let mut subscriptions: HashMap<String, Subscription> = self
+                    .books
+                    .iter()
+                    .filter(|((name, _), _)| *name == key.0)
+                    .map(|((_, instrument), subscription)| {
+                        (instrument.clone(), subscription.clone())
+                    })
+                    .collect();
+                subscriptions.insert(key.1.clone(), subscription.clone());
+                let handle = spawn(
+                    provider.clone(),
+                    venue,
+                    subscriptions,
+                    Backoff::new(&self.reconnect),
+                    FeedOptions::new(provider, &self.exchange),
+                );
+                self.providers.insert(key.0.clone(), handle);
+            }
+        }
+
+        let order_book = subscription.order_book.clone();
+        self.books.insert(key, subscription);
+        Ok(order_book)
+    }
+
+    /// Stop feeding the order book of the instrument, the provider connection stays open.
+    pub async fn remove(&mut self, provider: &ProviderName, instrument: &str) -> Result<(), Error> {
+        let key = (provider.name(), normalize_instrument(instrument));
+        if self.books.remove(&key).is_none() {
+            return Err(Error::NotAdded {
+                provider: provider.name(),
+                instrument: instrument.to_string(),
+            });
+        }
+        if let Some(handle) = self.providers.get(&key.0) {
+            handle.remove(instrument).await?;
+        }
+        Ok(())
+    }
+
+    /// Order book of the instrument, if added.
+    pub fn get(&self, provider: &ProviderName, instrument: &str) -> Option<SharedOrderBook> {
+        let key = (provider.name(), normalize_instrument(instrument));
+        self.books
+            .get(&key)
+            .map(|subscription| subscription.order_book.clone())
+    }
+
+    /// Added (provider, normalized instrument) pairs in alphabetical order.
+    pub fn instruments(&self) -> Vec<(String, String)> {
+        let mut instruments: Vec<(String, String)> = self.books.keys().cloned().collect();
+        instruments.sort();
+        instruments
+    }
+
+    /// Connection events of the provider, if connected.
+    pub fn events(&self, provider: &ProviderName) -> Option<broadcast::Receiver<ProviderEvent>> {
+        self.providers
+            .get(&provider.name())
+            .map(|handle| handle.events())
+    }
+
+    /// Live connection status of the provider, if connected.
+    pub fn health(&self, provider: &ProviderName) -> Option<FeedHealth> {
+        self.providers
+            .get(&provider.name())
+            .map(|handle| handle.health())
+    }
+
+    /// Wait until one of the providers stops, see `ProviderHandle::stopped`.
+    ///
+    /// Returns at once when no provider is connected.
+    pub async fn stopped(&self) -> Result<(), Arc<Error>> {
+        if self.providers.is_empty() {
+            return Ok(());
+        }
+        let stopped = self
+            .providers
+            .values()
+            .map(|handle| Box::pin(handle.stopped()));
+        future::select_all(stopped).await.0
+    }
+
+    /// Unsubscribe from all instruments and stop all providers.
+    ///
+    /// Every provider is stopped, the first failure is returned once all are.
+    pub async fn stop(&mut self) -> Result<(), Error> {
+        let mut result = Ok(());
+        for (name, handle) in self.providers.drain() {
+            if let Err(e) = handle.stop().await {
+                warn!("Failed to stop {}: {}", name, e);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        self.books.clear();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use futures::{channel::mpsc, future::BoxFuture, FutureExt, StreamExt};
+
+    use super::*;
+    use crate::core::{
+        fixed_point::{Price, Quantity},
+        messages::{Message, OrderBookSnapshot, OrderBookUpdate, Side},
+        order_book::CrossState,
+    };
+    use crate::providers::{MessageStream, Provider};
+    use crate::utils::config::CrossPolicy;
+
+    type Feed = mpsc::UnboundedReceiver<Result<(String, Message), Error>>;
+
+    /// Provider replaying the messages of the test and recording the subscriptions.
+    #[derive(Default)]
+    struct MockProvider {
+        feed: Arc<Mutex<Option<Feed>>>,
+        subscribed: Arc<Mutex<Vec<String>>>,
+        unreachable: bool,
+    }
+
+    impl Provider for MockProvider {
+        fn connect(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+            let unreachable = self.unreachable;
+            async move {
+                if unreachable {
+                    return Err(Error::Protocol("Unreachable".to_string()));
+                }
+                Ok(())
+            }
+            .boxed()
+        }
+
+        fn subscribe<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+            self.subscribed.lock().unwrap().push(instrument.to_string());
+            async { Ok(()) }.boxed()
+        }
+
+        fn unsubscribe<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+            self.subscribed.lock().unwrap().retain(|i| i != instrument);
+            async { Ok(()) }.boxed()
+        }
+
+        fn messages(&mut self) -> Result<MessageStream, Error> {
+            let feed = self.feed.lock().unwrap().take().unwrap();
+            let received = |(instrument, message)| (instrument, message, Instant::now());
+            Ok(feed.map(move |message| message.map(received)).boxed())
+        }
+    }
+
+    fn snapshot(price: f64) -> Message {
+        Message::Snapshot(OrderBookSnapshot {
+            bids: vec![OrderBookUpdate {
+                price: Price::from_f64(price),
+                quantity: Quantity::from_f64(1.0),
+                side: Side::Buy,
+            }],
+            asks: vec![],
+            sequence: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_books_share_one_connection() {
+        let (tx, rx) = mpsc::unbounded();
+        let feed = Arc::new(Mutex::new(Some(rx)));
+        let subscribed = Arc::new(Mutex::new(vec![]));
+        let connections = Arc::new(Mutex::new(0));
+
+        let mut registry = ProviderRegistry::empty();
+        let mock = ProviderName::Custom("Mock".to_string());
+        let (factory_feed, factory_subscribed, factory_connections) =
+            (feed.clone(), subscribed.clone(), connections.clone());
+        registry.register(mock.clone(), move |_| {
+            *factory_connections.lock().unwrap() += 1;
+            Box::new(MockProvider {
+                feed: factory_feed.clone(),
+                subscribed: factory_subscribed.clone(),
+                ..Default::default()
+            })
+        });
+
+        let exchange = ExchangeConfig {
+            depth_limit: Some(10),
+            ..Default::default()
+        };
+        let mut manager = BookManager::with_registry(registry, exchange, Default::default());
+
+        let btc = manager.add(&mock, "BTC-USD").await.unwrap();
+        let eth = manager.add(&mock, "ETH-USD").await.unwrap();
+        let mut btc_changes = btc.subscribe(None);
+        let mut eth_changes = eth.subscribe(None);
+
+        tx.unbounded_send(Ok(("btcusd".to_string(), snapshot(64000.0))))
+            .unwrap();
+        tx.unbounded_send(Ok(("ethusd".to_string(), snapshot(3000.0))))
+            .unwrap();
+
+        assert_eq!(
+            btc_changes.next().await.best_bid,
+            Some(Price::from_f64(64000.0))
+        );
+        assert_eq!(
+            eth_changes.next().await.best_bid,
+            Some(Price::from_f64(3000.0))
+        );
+        assert_eq!(*connections.lock().unwrap(), 1);
+        assert_eq!(
+            manager.instruments(),
+            vec![
+                ("mock".to_string(), "btcusd".to_string()),
+                ("mock".to_string(), "ethusd".to_string())
+            ]
+        );
+
+        manager.remove(&mock, "ETH-USD").await.unwrap();
+        assert!(manager.get(&mock, "eth-usd").is_none());
+        manager.stop().await.unwrap();
+        // the provider task unsubscribes from everything once it runs
+        tokio::task::yield_now().await;
+        assert!(subscribed.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_crossed_book_is_purged() {
+        let (tx, rx) = mpsc::unbounded();
+        let feed = Arc::new(Mutex::new(Some(rx)));
+        let mut registry = ProviderRegistry::empty();
+        let mock = ProviderName::Custom("Mock".to_string());
+        registry.register(mock.clone(), move |_| {
+            Box::new(MockProvider {
+                feed: feed.clone(),
+                ..Default::default()
+            })
+        });
+
+        let exchange = ExchangeConfig {
+            depth_limit: Some(10),
+            on_cross: Some(CrossPolicy::Purge),
+            ..Default::default()
+        };
+        let mut manager = BookManager::with_registry(registry, exchange, Default::default());
+        let btc = manager.add(&mock, "BTC-USD").await.unwrap();
+        let mut events = manager.events(&mock).unwrap();
+
+        let level = |price: f64, side: Side| OrderBookUpdate {
+            price: Price::from_f64(price),
+            quantity: Quantity::from_f64(1.0),
+            side,
+        };
+        let crossed = Message::Snapshot(OrderBookSnapshot {
+            bids: vec![level(99.0, Side::Buy), level(101.0, Side::Buy)],
+            asks: vec![level(100.0, Side::Sell), level(102.0, Side::Sell)],
+            sequence: None,
+        });
+        tx.unbounded_send(Ok(("btcusd".to_string(), crossed)))
+            .unwrap();
+
+        let event = loop {
+            match events.recv().await.unwrap() {
+                ProviderEvent::Connected => continue,
+                event => break event,
+            }
+        };
+        assert_eq!(
+            event,
+            ProviderEvent::Crossed {
+                instrument: "BTC-USD".to_string(),
+                state: CrossState::Crossed,
+                best_bid: Some(Price::from_f64(101.0)),
+                best_ask: Some(Price::from_f64(100.0)),
+            }
+        );
+        // the purge happens right after the event
+        tokio::task::yield_now().await;
+        assert_eq!(btc.cross_state().await, CrossState::Normal);
+        assert_eq!(btc.get_best_bid().await, Some(Price::from_f64(99.0)));
+        assert_eq!(btc.get_best_ask().await, Some(Price::from_f64(102.0)));
+        manager.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_book_without_depth_limit() {
+        let (tx, rx) = mpsc::unbounded();
+        let feed = Arc::new(Mutex::new(Some(rx)));
+        let mut registry = ProviderRegistry::empty();
+        let mock = ProviderName::Custom("Mock".to_string());
+        registry.register(mock.clone(), move |_| {
+            Box::new(MockProvider {
+                feed: feed.clone(),
+                ..Default::default()
+            })
+        });
+
+        // no depth limit keeps every level instead of none
+        let mut manager =
+            BookManager::with_registry(registry, Default::default(), Default::default());
+        let btc = manager.add(&mock, "BTC-USD").await.unwrap();
+        let mut changes = btc.subscribe(None);
+        tx.unbounded_send(Ok(("btcusd".to_string(), snapshot(64000.0))))
+            .unwrap();
+
+        let snapshot = changes.next().await;
+        assert_eq!(snapshot.best_bid, Some(Price::from_f64(64000.0)));
+        assert_eq!(snapshot.bids.len(), 1);
+
+        manager.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stopped_provider_is_started_again() {
+        let (tx, rx) = mpsc::unbounded();
+        let feed = Arc::new(Mutex::new(Some(rx)));
+        let connections = Arc::new(Mutex::new(0));
+        let mut registry = ProviderRegistry::empty();
+        let mock = ProviderName::Custom("Mock".to_string());
+        let factory_connections = connections.clone();
+        registry.register(mock.clone(), move |_| {
+            let mut connections = factory_connections.lock().unwrap();
+            *connections += 1;
+            // the first provider never connects and gives up
+            Box::new(MockProvider {
+                feed: feed.clone(),
+                unreachable: *connections == 1,
+                ..Default::default()
+            })
+        });
+
+        let reconnect = ReconnectConfig {
+            max_attempts: Some(1),
+            initial_delay_ms: Some(1),
+            ..Default::default()
+        };
+        let mut manager = BookManager::with_registry(registry, Default::default(), reconnect);
+        let btc = manager.add(&mock, "BTC-USD").await.unwrap();
+        assert!(manager.stopped().await.is_err());
+
+        // the next instrument starts a new provider, feeding the earlier book as well
+        let eth = manager.add(&mock, "ETH-USD").await.unwrap();
+        let (mut btc_changes, mut eth_changes) = (btc.subscribe(None), eth.subscribe(None));
+        tx.unbounded_send(Ok(("btcusd".to_string(), snapshot(64000.0))))
+            .unwrap();
+        tx.unbounded_send(Ok(("ethusd".to_string(), snapshot(3000.0))))
+            .unwrap();
+        assert_eq!(
+            btc_changes.next().await.best_bid,
+            Some(Price::from_f64(64000.0))
+        );
+        assert_eq!(
+            eth_changes.next().await.best_bid,
+            Some(Price::from_f64(3000.0))
+        );
+        assert_eq!(*connections.lock().unwrap(), 2);
+
+        manager.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stop_all_providers() {
+        let (_tx, rx) = mpsc::unbounded();
+        let feed = Arc::new(Mutex::new(Some(rx)));
+        let subscribed = Arc::new(Mutex::new(vec![]));
+        let mut registry = ProviderRegistry::empty();
+        let (stopped, running) = (
+            ProviderName::Custom("Stopped".to_string()),
+            ProviderName::Custom("Running".to_string()),
+        );
+        registry.register(stopped.clone(), |_| {
+            Box::new(MockProvider {
+                unreachable: true,
+                ..Default::default()
+            })
+        });
+        let factory_subscribed = subscribed.clone();
+        registry.register(running.clone(), move |_| {
+            Box::new(MockProvider {
+                feed: feed.clone(),
+                subscribed: factory_subscribed.clone(),
+                ..Default::default()
+            })
+        });
+
+        let reconnect = ReconnectConfig {
+            max_attempts: Some(1),
+            initial_delay_ms: Some(1),
+            ..Default::default()
+        };
+        let mut manager = BookManager::with_registry(registry, Default::default(), reconnect);
+        manager.add(&stopped, "BTC-USD").await.unwrap();
+        manager.add(&running, "BTC-USD").await.unwrap();
+        assert!(manager.stopped().await.is_err());
+        while subscribed.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        // the failure of one provider does not leave the other one subscribed
+        assert!(matches!(manager.stop().await, Err(Error::Stopped)));
+        tokio::task::yield_now().await;
+        assert!(subscribed.lock().unwrap().is_empty());
+        assert!(manager.instruments().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_provider() {
+        let mut manager = BookManager::with_registry(
+            ProviderRegistry::empty(),
+            Default::default(),
+            Default::default(),
+        );
+        assert!(manager
+            .add(&ProviderName::Deribit, "BTC-PERPETUAL")
+            .await
+            .is_err());
+        assert!(manager.instruments().is_empty());
+    }
+}
@@ -1,41 +1,565 @@
+mod backoff;
 mod bitstamp;
 mod deribit;
+mod health;
+mod manager;
+mod recorder;
+mod registry;
+mod replay;
+mod ws;
+
+pub use backoff::Backoff;
+pub use bitstamp::Bitstamp;
+pub use deribit::Deribit;
+pub use health::{FeedHealth, HealthStatus};
+pub use manager::BookManager;
+pub use recorder::{is_compressed, RecordedFrame, Recorder};
+pub use registry::{ProviderFactory, ProviderRegistry};
+pub use replay::Replay;
 
 use crate::{
-    core::SharedOrderBook,
-    utils::config::{Config, Provider},
+    core::{
+        fixed_point::Price,
+        messages::Message,
+        order_book::{CrossState, SequenceStatus},
+        SharedOrderBook,
+    },
+    error::Error,
+    utils::config::{
+        normalize_instrument, Config, CrossPolicy, ExchangeConfig, Provider as ProviderName,
+    },
+};
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
+use health::Monitor;
+use log::{debug, error, info, warn};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, Receiver, Sender},
+        watch,
+    },
+    time::{self, Instant, Interval, MissedTickBehavior},
 };
-use tokio::sync::mpsc::{self, Sender};
 
-pub fn subscribe_to_provider(config: Config, order_book: SharedOrderBook) -> Option<Sender<()>> {
-    let provider = config.provider.name.unwrap_or(Provider::None);
-    let (stop_tx, stop_rx) = mpsc::channel(1);
+const EVENTS_CAPACITY: usize = 16;
+const COMMANDS_CAPACITY: usize = 16;
+/// Heartbeat interval of the connections when `heartbeat_secs` is not configured.
+pub const DEFAULT_HEARTBEAT_SECS: u64 = 10;
+
+/// Stream of normalized order book messages of a single provider connection,
+/// tagged with the normalized name of the instrument (see `normalize_instrument`)
+/// and the time the frame of the message was read from the connection.
+pub type MessageStream = BoxStream<'static, Result<(String, Message, std::time::Instant), Error>>;
+
+/// A market data venue which is able to feed the order book.
+///
+/// Implementations translate the venue protocol into normalized `Message`s,
+/// so the order book does not depend on any exchange specifics.
+pub trait Provider: Send {
+    /// Open the connection to the venue.
+    fn connect(&mut self) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Subscribe to the order book channel of the instrument.
+    ///
+    /// Called once per instrument, many instruments share the same connection.
+    fn subscribe<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Unsubscribe from the order book channel of the instrument.
+    fn unsubscribe<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Take the message stream of the current connection. Can be taken once per `connect`.
+    fn messages(&mut self) -> Result<MessageStream, Error>;
+
+    /// Request a fresh snapshot after a sequence gap.
+    ///
+    /// By default resubscribes, as venues send a snapshot first on subscription.
+    fn resync<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        async move {
+            self.unsubscribe(instrument).await?;
+            self.subscribe(instrument).await
+        }
+        .boxed()
+    }
+
+    /// Record the inbound frames of the connection, ignored by default.
+    fn set_recorder(&mut self, _recorder: Recorder) {}
+
+    /// Check that the connection is alive, called every heartbeat interval.
+    ///
+    /// Fails when the venue stopped answering, so the supervisor reconnects. Does nothing by default.
+    fn heartbeat(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        async { Ok(()) }.boxed()
+    }
+}
+
+/// Connection lifecycle events of a provider.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProviderEvent {
+    /// Connected and subscribed to the instruments.
+    Connected,
+    /// Connection lost, the order books were reset.
+    Disconnected { reason: String },
+    /// Next connection attempt is scheduled after the delay.
+    Reconnecting { attempt: u32, delay: Duration },
+    /// The book of the instrument became crossed or locked, remediated per `CrossPolicy`.
+    Crossed {
+        instrument: String,
+        state: CrossState,
+        best_bid: Option<Price>,
+        best_ask: Option<Price>,
+    },
+    /// No message of the instrument for the age, the book is marked stale and the provider reconnects.
+    Stale { instrument: String, age: Duration },
+}
+
+/// Policies of the supervisor, taken from the exchange configuration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FeedOptions {
+    on_cross: CrossPolicy,
+    /// Age of the last message of an instrument before the book is stale, no watchdog if not set.
+    stale_after: Option<Duration>,
+    heartbeat: Duration,
+}
+
+impl FeedOptions {
+    fn new(provider: &ProviderName, config: &ExchangeConfig) -> Self {
+        let stale_after = match provider {
+            // gaps of a recording are replayed as they were, they do not mean a frozen feed
+            ProviderName::Replay => None,
+            _ => config.stale_after_ms.map(Duration::from_millis),
+        };
+        FeedOptions {
+            on_cross: config.on_cross.unwrap_or_default(),
+            stale_after,
+            heartbeat: Duration::from_secs(config.heartbeat_secs.unwrap_or(DEFAULT_HEARTBEAT_SECS)),
+        }
+    }
+}
+
+/// Requests to a running provider task.
+enum Command {
+    Add(String, SharedOrderBook),
+    Remove(String),
+    Stop,
+}
+
+/// Order book fed by the provider, keyed by the normalized instrument.
+#[derive(Clone)]
+struct Subscription {
+    instrument: String,
+    order_book: SharedOrderBook,
+}
+
+type Subscriptions = HashMap<String, Subscription>;
+
+/// Outcome of the provider task, `None` while it runs.
+type Exit = Option<Result<(), Arc<Error>>>;
+
+/// Handle of a running provider task.
+pub struct ProviderHandle {
+    commands: Sender<Command>,
+    monitor: Monitor,
+    exit: watch::Receiver<Exit>,
+}
+
+impl ProviderHandle {
+    /// Feed the order book from the instrument over the same connection.
+    pub async fn add(&self, instrument: &str, order_book: SharedOrderBook) -> Result<(), Error> {
+        self.send(Command::Add(instrument.to_string(), order_book))
+            .await
+    }
+
+    /// Unsubscribe from the instrument, the connection stays open.
+    pub async fn remove(&self, instrument: &str) -> Result<(), Error> {
+        self.send(Command::Remove(instrument.to_string())).await
+    }
+
+    /// Unsubscribe from all instruments and stop the provider task.
+    pub async fn stop(&self) -> Result<(), Error> {
+        self.send(Command::Stop).await
+    }
+
+    /// Subscribe to the connection events emitted from now on.
+    pub fn events(&self) -> broadcast::Receiver<ProviderEvent> {
+        self.monitor.events.subscribe()
+    }
+
+    /// Live connection status of the provider.
+    pub fn health(&self) -> FeedHealth {
+        self.monitor.health.clone()
+    }
+
+    /// The provider task has ended, commands are not handled anymore.
+    pub fn is_stopped(&self) -> bool {
+        self.exit.borrow().is_some() || self.commands.is_closed()
+    }
+
+    /// Wait until the provider task ends, after `stop` or when it runs out of reconnect attempts.
+    ///
+    /// Returns the failure which ended the task, so the application can react to a lost feed.
+    pub async fn stopped(&self) -> Result<(), Arc<Error>> {
+        let mut exit = self.exit.clone();
+        let result = match exit.wait_for(Option::is_some).await {
+            Ok(exit) => exit.clone().unwrap_or(Ok(())),
+            // the task panicked
+            Err(_) => Err(Arc::new(Error::Stopped)),
+        };
+        result
+    }
+
+    async fn send(&self, command: Command) -> Result<(), Error> {
+        self.commands
+            .send(command)
+            .await
+            .map_err(|_| Error::Stopped)
+    }
+}
+
+pub fn subscribe_to_provider(
+    config: Config,
+    order_book: SharedOrderBook,
+) -> Result<ProviderHandle, Error> {
+    subscribe_with_registry(&registry_with_replay(&config), config, order_book)
+}
+
+/// Built-in providers and the replay of the configured recording.
+fn registry_with_replay(config: &Config) -> ProviderRegistry {
+    let mut registry = ProviderRegistry::new();
+    let replay = config.replay.clone();
+    registry.register(ProviderName::Replay, move |exchange| {
+        Box::new(Replay::new(exchange.clone(), replay.clone()))
+    });
+    registry
+}
+
+/// Subscribe to the configured provider, resolving it from the given registry.
+pub fn subscribe_with_registry(
+    registry: &ProviderRegistry,
+    config: Config,
+    order_book: SharedOrderBook,
+) -> Result<ProviderHandle, Error> {
+    let provider_name = config.provider.name.unwrap_or(ProviderName::None);
+    let mut provider = registry.create(&provider_name, &config.exchange)?;
+    if config.recorder.is_enabled() {
+        provider.set_recorder(Recorder::new(&config.recorder, &provider_name.name())?);
+    }
+    let instrument = config.exchange.instrument.clone().unwrap_or_default();
+    let subscriptions = HashMap::from([(
+        normalize_instrument(&instrument),
+        Subscription {
+            instrument,
+            order_book,
+        },
+    )]);
+
+    let options = FeedOptions::new(&provider_name, &config.exchange);
+
+    Ok(spawn(
+        provider_name,
+        provider,
+        subscriptions,
+        Backoff::new(&config.reconnect),
+        options,
+    ))
+}
+
+/// Run the provider in a background task, feeding the subscribed order books.
+fn spawn(
+    provider_name: ProviderName,
+    provider: Box<dyn Provider>,
+    subscriptions: Subscriptions,
+    backoff: Backoff,
+    options: FeedOptions,
+) -> ProviderHandle {
+    let (commands, commands_rx) = mpsc::channel(COMMANDS_CAPACITY);
+    let monitor = Monitor::new(&provider_name.name(), EVENTS_CAPACITY);
+    let (exit_tx, exit) = watch::channel(None);
 
+    let supervisor_monitor = monitor.clone();
     tokio::task::spawn(async move {
-        match provider {
-            Provider::Deribit => {
-                if let Err(e) =
-                    deribit::subscribe_to_order_book(order_book, &config.exchange, stop_rx).await
-                {
-                    eprintln!("Error subscribing to Deribit: {:?}", e);
-                }
+        let result = supervise(
+            provider,
+            subscriptions,
+            commands_rx,
+            backoff,
+            options,
+            supervisor_monitor,
+        )
+        .await;
+        if let Err(e) = &result {
+            error!("Provider {:?} stopped: {}", provider_name, e);
+        }
+        exit_tx.send_replace(Some(result.map_err(Arc::new)));
+    });
+
+    ProviderHandle {
+        commands,
+        monitor,
+        exit,
+    }
+}
+
+/// Keep the provider connected, reconnecting with backoff until stopped or out of attempts.
+async fn supervise(
+    mut provider: Box<dyn Provider>,
+    mut subscriptions: Subscriptions,
+    mut commands: Receiver<Command>,
+    mut backoff: Backoff,
+    options: FeedOptions,
+    monitor: Monitor,
+) -> Result<(), Error> {
+    loop {
+        let error = match run(
+            provider.as_mut(),
+            &mut subscriptions,
+            &mut commands,
+            &mut backoff,
+            options,
+            &monitor,
+        )
+        .await
+        {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        let reason = error.to_string();
+
+        // the books are stale from now on, do not show them as valid
+        error!("Disconnected from provider: {}", reason);
+        for subscription in subscriptions.values() {
+            subscription.order_book.reset().await;
+        }
+        monitor.emit(ProviderEvent::Disconnected { reason });
+
+        let Some(delay) = backoff.next_delay() else {
+            return Err(Error::GaveUp {
+                attempts: backoff.attempt(),
+                source: Box::new(error),
+            });
+        };
+        info!("Reconnecting in {:?}, attempt {}", delay, backoff.attempt());
+        monitor.emit(ProviderEvent::Reconnecting {
+            attempt: backoff.attempt(),
+            delay,
+        });
+
+        // instruments added or removed meanwhile are picked up on reconnect
+        let sleep = time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(Command::Add(instrument, order_book)) => {
+                        subscriptions.insert(
+                            normalize_instrument(&instrument),
+                            Subscription { instrument, order_book },
+                        );
+                    }
+                    Some(Command::Remove(instrument)) => {
+                        subscriptions.remove(&normalize_instrument(&instrument));
+                    }
+                    Some(Command::Stop) | None => return Ok(()),
+                },
+                _ = &mut sleep => break,
             }
-            Provider::Bitstamp => {
-                if let Err(e) =
-                    bitstamp::subscribe_to_order_book(order_book.clone(), &config.exchange, stop_rx)
+        }
+    }
+}
+
+/// Drive the order books from a single connection until a stop signal (`Ok`)
+/// or a connection failure (`Err`).
+async fn run(
+    provider: &mut dyn Provider,
+    subscriptions: &mut Subscriptions,
+    commands: &mut Receiver<Command>,
+    backoff: &mut Backoff,
+    options: FeedOptions,
+    monitor: &Monitor,
+) -> Result<(), Error> {
+    provider.connect().await?;
+    for Subscription { instrument, .. } in subscriptions.values() {
+        provider
+            .subscribe(instrument)
+            .await
+            .map_err(|e| Error::subscribe(instrument, e))?;
+    }
+    let mut messages = provider.messages()?;
+    monitor.emit(ProviderEvent::Connected);
+    let mut healthy = false;
+    // last reported cross state per instrument, so a lasting cross is reported once
+    let mut crosses: HashMap<String, CrossState> = HashMap::new();
+    // last message per instrument, watched against `stale_after`
+    let mut last_seen: HashMap<String, Instant> = subscriptions
+        .keys()
+        .map(|key| (key.clone(), Instant::now()))
+        .collect();
+    let mut heartbeat = interval_after(options.heartbeat);
+    let mut watchdog = interval_after(
+        options
+            .stale_after
+            .map_or(options.heartbeat, watchdog_period),
+    );
+
+    loop {
+        tokio::select! {
+            // commands first, so a busy feed does not delay subscriptions or the stop
+            biased;
+
+            command = commands.recv() => match command {
+                Some(Command::Add(instrument, order_book)) => {
+                    let key = normalize_instrument(&instrument);
+                    if subscriptions.contains_key(&key) {
+                        warn!("Already subscribed to {}", instrument);
+                        continue;
+                    }
+                    provider
+                        .subscribe(&instrument)
                         .await
-                {
-                    eprintln!("Error subscribing to Bitstamp: {:?}", e);
+                        .map_err(|e| Error::subscribe(&instrument, e))?;
+                    last_seen.insert(key.clone(), Instant::now());
+                    subscriptions.insert(key, Subscription { instrument, order_book });
+                }
+                Some(Command::Remove(instrument)) => {
+                    let key = normalize_instrument(&instrument);
+                    last_seen.remove(&key);
+                    if subscriptions.remove(&key).is_some() {
+                        provider.unsubscribe(&instrument).await?;
+                    }
                 }
+                Some(Command::Stop) | None => {
+                    for subscription in subscriptions.values() {
+                        let instrument = &subscription.instrument;
+                        if let Err(e) = provider.unsubscribe(instrument).await {
+                            warn!("Failed to unsubscribe from {}: {:?}", instrument, e);
+                        }
+                    }
+                    return Ok(());
+                }
+            },
+            _ = heartbeat.tick() => provider.heartbeat().await?,
+            _ = watchdog.tick(), if options.stale_after.is_some() => {
+                let stale_after = options.stale_after.unwrap_or_default();
+                let stale = last_seen
+                    .iter()
+                    .map(|(key, seen)| (key, seen.elapsed()))
+                    .find(|(_, age)| *age >= stale_after);
+                let Some((key, age)) = stale else {
+                    continue;
+                };
+                let Some(Subscription { instrument, order_book }) = subscriptions.get(key) else {
+                    continue;
+                };
+
+                // the connection may be open but the feed frozen, start over
+                warn!("No messages for {} in {:?}, reconnecting", instrument, age);
+                order_book.mark_stale();
+                monitor.emit(ProviderEvent::Stale {
+                    instrument: instrument.clone(),
+                    age,
+                });
+                return Err(Error::Stale {
+                    instrument: instrument.clone(),
+                    age,
+                });
             }
-            _ => {
-                panic!(
-                    "Unsupported provider. Only Deribit and Bitstamp are supported. Provided: {:?}",
-                    provider
-                )
+            message = messages.next() => {
+                let (key, message, received) = match message {
+                    Some(message) => message?,
+                    None => {
+                        return Err(Error::Protocol(
+                            "Connection closed by the provider".to_string(),
+                        ))
+                    }
+                };
+                monitor.message();
+
+                // the connection delivers data, next failure starts a fresh backoff
+                if !healthy {
+                    backoff.reset();
+                    healthy = true;
+                }
+
+                // late messages of a removed instrument
+                let Some(Subscription { instrument, order_book }) = subscriptions.get(&key) else {
+                    debug!("Skipped message of unsubscribed instrument {}", key);
+                    continue;
+                };
+                last_seen.insert(key.clone(), Instant::now());
+
+                match message {
+                    Message::Trades(trades) => {
+                        // trades do not change the book
+                        order_book.trades().record(trades);
+                        monitor.applied(received);
+                        continue;
+                    }
+                    Message::Snapshot(snapshot) => {
+                        order_book.apply_snapshot(snapshot).await;
+                    }
+                    Message::Update(update) => {
+                        order_book.process_update(update).await;
+                    }
+                    Message::Delta(delta) => {
+                        if let SequenceStatus::Gap { expected, received } =
+                            order_book.apply_delta(delta).await
+                        {
+                            warn!(
+                                "Sequence gap for {} (expected {}, received {}), requesting snapshot",
+                                instrument, expected, received
+                            );
+                            let instrument = instrument.clone();
+                            provider.resync(&instrument).await?;
+                            continue;
+                        }
+                    }
+                }
+                monitor.applied(received);
+
+                let state = order_book.cross_state().await;
+                let reported = crosses.insert(key, state).unwrap_or_default();
+                if state.is_normal() || state == reported {
+                    continue;
+                }
+
+                let (best_bid, best_ask) =
+                    (order_book.get_best_bid().await, order_book.get_best_ask().await);
+                warn!(
+                    "{:?} book for {} (bid {:?}, ask {:?}), remediation: {:?}",
+                    state, instrument, best_bid, best_ask, options.on_cross
+                );
+                monitor.emit(ProviderEvent::Crossed {
+                    instrument: instrument.clone(),
+                    state,
+                    best_bid,
+                    best_ask,
+                });
+
+                match options.on_cross {
+                    CrossPolicy::Log => {}
+                    CrossPolicy::Resync => {
+                        // the book is not trusted until the fresh snapshot
+                        order_book.reset().await;
+                        let instrument = instrument.clone();
+                        provider.resync(&instrument).await?;
+                    }
+                    CrossPolicy::Purge => {
+                        order_book.purge_crossing().await;
+                    }
+                }
             }
         }
-    });
+    }
+}
+
+/// Interval ticking every period, the first tick after one period.
+fn interval_after(period: Duration) -> Interval {
+    let mut interval = time::interval_at(Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval
+}
 
-    Some(stop_tx.clone())
+/// Check period of the staleness watchdog, a fraction of the threshold to detect it in time.
+fn watchdog_period(stale_after: Duration) -> Duration {
+    (stale_after / 4).max(Duration::from_millis(1))
 }
@@ -0,0 +1,381 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::utils::config::RecorderConfig;
+
+const DEFAULT_MAX_FILE_BYTES: u64 = 100 * 1024 * 1024;
+const DEFAULT_MAX_FILE_SECS: u64 = 3600;
+const FILE_EXTENSION: &str = "ndjson";
+const ZSTD_EXTENSION: &str = "zst";
+/// Frames queued for the writer thread, beyond them frames are dropped rather than
+/// stalling the feed.
+const QUEUE_CAPACITY: usize = 65_536;
+
+/// Inbound frame of a provider connection, stored as one JSON object per line.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    /// Receive time, in microseconds since the Unix epoch.
+    pub received_us: u64,
+    pub provider: String,
+    /// Channel of the frame, empty for frames without one, e.g. connection events.
+    pub channel: String,
+    /// Frame text as received from the venue.
+    pub frame: String,
+}
+
+/// Channel of a venue frame, `channel` for Bitstamp and `params.channel` for Deribit.
+#[derive(Deserialize, Default)]
+struct RawChannel {
+    #[serde(default)]
+    channel: Option<String>,
+    #[serde(default)]
+    params: Option<Box<RawChannel>>,
+}
+
+impl RawChannel {
+    fn of(frame: &str) -> String {
+        let raw = serde_json::from_str::<RawChannel>(frame).unwrap_or_default();
+        raw.channel
+            .or_else(|| raw.params.and_then(|params| params.channel))
+            .unwrap_or_default()
+    }
+}
+
+/// Appends the inbound frames of a provider to newline-delimited JSON files.
+///
+/// A new file `{provider}-{received_us}.ndjson` is started in the directory when the current
+/// one exceeds the size or the age limit. With compression the files are zstd streams
+/// (`.ndjson.zst`), finished on rotation and when the last clone of the recorder is dropped.
+///
+/// The files are written by a dedicated thread, so disk latency never holds the feed.
+#[derive(Clone)]
+pub struct Recorder {
+    provider: String,
+    writer: Arc<Writer>,
+}
+
+impl Recorder {
+    /// Recorder of the provider writing to the configured directory, created if missing.
+    pub fn new(config: &RecorderConfig, provider: &str) -> Result<Self, Error> {
+        let directory = PathBuf::from(config.path.as_deref().unwrap_or("."));
+        fs::create_dir_all(&directory)?;
+
+        let file = RecordingFile {
+            directory,
+            max_bytes: config.max_file_bytes.unwrap_or(DEFAULT_MAX_FILE_BYTES),
+            max_age: Duration::from_secs(config.max_file_secs.unwrap_or(DEFAULT_MAX_FILE_SECS)),
+            compress: config.compress.unwrap_or_default(),
+            sink: None,
+            written: 0,
+            opened: Instant::now(),
+        };
+        let (frames, queue) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let name = provider.to_string();
+        let thread = thread::Builder::new()
+            .name(format!("recorder-{}", provider))
+            .spawn(move || write_frames(file, &name, queue))?;
+
+        Ok(Recorder {
+            provider: provider.to_string(),
+            writer: Arc::new(Writer {
+                frames: Some(frames),
+                thread: Some(thread),
+            }),
+        })
+    }
+
+    /// Record the frame with the current time, failures are logged and do not stop the feed.
+    pub fn record(&self, frame: &str) {
+        let Some(frames) = &self.writer.frames else {
+            return;
+        };
+        match frames.try_send((now_us(), frame.to_string())) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!("Recorder of {} is behind, dropped a frame", self.provider)
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                warn!("Recorder of {} is stopped, dropped a frame", self.provider)
+            }
+        }
+    }
+}
+
+/// Queue of the writer thread, finished and joined with the last clone of the recorder.
+struct Writer {
+    frames: Option<SyncSender<(u64, String)>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        // the thread writes the queued frames and finishes the file once the queue closes
+        self.frames.take();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("Recorder thread panicked");
+            }
+        }
+    }
+}
+
+/// Write the queued frames, flushing once per burst rather than per frame.
+fn write_frames(mut file: RecordingFile, provider: &str, queue: Receiver<(u64, String)>) {
+    let record = |file: &mut RecordingFile, (received_us, frame): (u64, String)| {
+        let frame = RecordedFrame {
+            received_us,
+            provider: provider.to_string(),
+            channel: RawChannel::of(&frame),
+            frame,
+        };
+        if let Err(e) = file.write(&frame) {
+            warn!("Failed to record {} frame: {:?}", provider, e);
+        }
+    };
+
+    while let Ok(frame) = queue.recv() {
+        record(&mut file, frame);
+        for frame in queue.try_iter() {
+            record(&mut file, frame);
+        }
+        if let Err(e) = file.flush() {
+            warn!("Failed to flush {} recording: {:?}", provider, e);
+        }
+    }
+}
+
+enum Sink {
+    Plain(BufWriter<File>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Sink {
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        match self {
+            Sink::Plain(writer) => writer.write_all(line),
+            Sink::Zstd(encoder) => encoder.write_all(line),
+        }
+    }
+
+    /// Flush an uncompressed recording, so a crash loses at most the last burst of frames.
+    /// A zstd stream is only readable once finished.
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(writer) => writer.flush(),
+            Sink::Zstd(_) => Ok(()),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Sink::Plain(mut writer) => writer.flush(),
+            Sink::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+/// Current file of the recorder with its rotation state.
+struct RecordingFile {
+    directory: PathBuf,
+    max_bytes: u64,
+    max_age: Duration,
+    compress: bool,
+    sink: Option<Sink>,
+    /// Uncompressed bytes written to the current file.
+    written: u64,
+    opened: Instant,
+}
+
+impl RecordingFile {
+    fn write(&mut self, frame: &RecordedFrame) -> Result<(), Error> {
+        if self.sink.is_none()
+            || self.written >= self.max_bytes
+            || self.opened.elapsed() >= self.max_age
+        {
+            self.rotate(frame)?;
+        }
+
+        let mut line = serde_json::to_vec(frame)?;
+        line.push(b'\n');
+        if let Some(sink) = self.sink.as_mut() {
+            sink.write_line(&line)?;
+        }
+        self.written += line.len() as u64;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.sink.as_mut() {
+            Some(sink) => sink.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn rotate(&mut self, frame: &RecordedFrame) -> Result<(), Error> {
+        if let Some(sink) = self.sink.take() {
+            sink.finish()?;
+        }
+
+        let path = self.file_path(&frame.provider, frame.received_us);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let writer = BufWriter::new(file);
+        self.sink = Some(if self.compress {
+            Sink::Zstd(zstd::Encoder::new(writer, 0)?)
+        } else {
+            Sink::Plain(writer)
+        });
+        self.written = 0;
+        self.opened = Instant::now();
+
+        info!("Recording frames to {}", path.display());
+        Ok(())
+    }
+
+    fn file_path(&self, provider: &str, received_us: u64) -> PathBuf {
+        let mut name = format!("{}-{}.{}", provider, received_us, FILE_EXTENSION);
+        if self.compress {
+            name = format!("{}.{}", name, ZSTD_EXTENSION);
+        }
+        self.directory.join(name)
+    }
+}
+
+impl Drop for RecordingFile {
+    fn drop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            if let Err(e) = sink.finish() {
+                warn!("Failed to finish recording: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Whether the file is a compressed recording, by its extension.
+pub fn is_compressed(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == ZSTD_EXTENSION)
+}
+
+/// Recording files of the path in name order, the path itself if it is a file.
+pub fn recording_files(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = fs::read_dir(path)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<PathBuf>, io::Error>>()?;
+    files.retain(|file| {
+        file.is_file()
+            && file
+                .to_string_lossy()
+                .contains(&format!(".{}", FILE_EXTENSION))
+    });
+    files.sort();
+    Ok(files)
+}
+
+/// Frames of a recording file in the recorded order.
+pub fn read_frames(
+    path: &Path,
+) -> Result<impl Iterator<Item = Result<RecordedFrame, Error>>, Error> {
+    let file = File::open(path)?;
+    let reader: Box<dyn Read + Send> = if is_compressed(path) {
+        Box::new(zstd::Decoder::new(file)?)
+    } else {
+        Box::new(file)
+    };
+
+    Ok(BufReader::new(reader)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?)))
+}
+
+fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("l2_recorder_{}_{}", name, now_us()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn recorded(directory: &Path) -> Vec<Vec<RecordedFrame>> {
+        recording_files(directory)
+            .unwrap()
+            .iter()
+            .map(|path| read_frames(path).unwrap().map(Result::unwrap).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_record_and_rotate_by_size() {
+        let directory = temp_dir("plain");
+        let config = RecorderConfig {
+            path: Some(directory.to_string_lossy().to_string()),
+            max_file_bytes: Some(1),
+            ..Default::default()
+        };
+        let recorder = Recorder::new(&config, "bitstamp").unwrap();
+
+        recorder.record(r#"{"event":"data","channel":"order_book_btcusd","data":{}}"#);
+        std::thread::sleep(Duration::from_millis(1));
+        recorder.record(r#"{"jsonrpc":"2.0","params":{"channel":"book.BTC-PERPETUAL.100ms"}}"#);
+        drop(recorder);
+
+        let files = recorded(&directory);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0][0].provider, "bitstamp");
+        assert_eq!(files[0][0].channel, "order_book_btcusd");
+        assert_eq!(files[1][0].channel, "book.BTC-PERPETUAL.100ms");
+        assert!(files[0][0].received_us <= files[1][0].received_us);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_record_compressed() {
+        let directory = temp_dir("zstd");
+        let config = RecorderConfig {
+            path: Some(directory.to_string_lossy().to_string()),
+            compress: Some(true),
+            ..Default::default()
+        };
+        let recorder = Recorder::new(&config, "deribit").unwrap();
+        recorder.record(r#"{"event":"bts:subscription_succeeded"}"#);
+        recorder.record(r#"{"event":"bts:subscription_succeeded"}"#);
+        drop(recorder);
+
+        let files = recorded(&directory);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].len(), 2);
+        assert_eq!(files[0][1].channel, "");
+        assert_eq!(
+            files[0][1].frame,
+            r#"{"event":"bts:subscription_succeeded"}"#
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
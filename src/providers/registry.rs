@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use crate::error::Error;
+use crate::utils::config::{ExchangeConfig, Provider as ProviderName};
+
+use super::{Bitstamp, Deribit, Provider};
+
+/// Builds a provider instance for the exchange configuration.
+pub type ProviderFactory = Box<dyn Fn(&ExchangeConfig) -> Box<dyn Provider> + Send + Sync>;
+
+/// Registry of the available providers keyed by the provider name.
+///
+/// Embedding applications can register their own venues and refer to them
+/// from the configuration by name, e.g. `PROVIDER_NAME=MyVenue`.
+pub struct ProviderRegistry {
+    factories: HashMap<String, ProviderFactory>,
+}
+
+impl ProviderRegistry {
+    /// Registry with the built-in Deribit and Bitstamp providers.
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register(ProviderName::Deribit, |config| {
+            Box::new(Deribit::new(config.clone()))
+        });
+        registry.register(ProviderName::Bitstamp, |config| {
+            Box::new(Bitstamp::new(config.clone()))
+        });
+        registry
+    }
+
+    /// Registry without any provider.
+    pub fn empty() -> Self {
+        ProviderRegistry {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Register a provider factory, replacing the previous one with the same name.
+    pub fn register<F>(&mut self, provider: ProviderName, factory: F)
+    where
+        F: Fn(&ExchangeConfig) -> Box<dyn Provider> + Send + Sync + 'static,
+    {
+        self.factories.insert(provider.name(), Box::new(factory));
+    }
+
+    /// Create the provider registered under the name, a configuration error if there is none.
+    pub fn create(
+        &self,
+        provider: &ProviderName,
+        config: &ExchangeConfig,
+    ) -> Result<Box<dyn Provider>, Error> {
+        let factory = self.factories.get(&provider.name()).ok_or_else(|| {
+            Error::Config(format!(
+                "Unsupported provider. Registered providers: {:?}. Provided: {:?}",
+                self.names(),
+                provider
+            ))
+        })?;
+        Ok(factory(config))
+    }
+
+    /// Names of the registered providers in alphabetical order.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.factories.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_providers() {
+        let registry = ProviderRegistry::new();
+        assert_eq!(registry.names(), vec!["bitstamp", "deribit"]);
+        assert!(registry
+            .create(&ProviderName::Deribit, &ExchangeConfig::default())
+            .is_ok());
+        assert!(registry
+            .create(&ProviderName::None, &ExchangeConfig::default())
+            .is_err_and(|e| matches!(e, Error::Config(_))));
+    }
+
+    #[test]
+    fn test_register_custom_provider() {
+        let mut registry = ProviderRegistry::empty();
+        registry.register(ProviderName::Custom("MyVenue".to_string()), |config| {
+            Box::new(Bitstamp::new(config.clone()))
+        });
+
+        let provider = "myvenue".parse::<ProviderName>().unwrap();
+        assert!(registry
+            .create(&provider, &ExchangeConfig::default())
+            .is_ok());
+        assert!(registry
+            .create(&ProviderName::Bitstamp, &ExchangeConfig::default())
+            .is_err());
+    }
+}
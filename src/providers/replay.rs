@@ -0,0 +1,302 @@
+use std::path::PathBuf;
+
+use futures::{future::BoxFuture, stream, FutureExt, StreamExt};
+use log::{info, warn};
+use tokio::{
+    sync::mpsc,
+    time::{self, Duration, Instant},
+};
+
+use super::{
+    bitstamp::{self, DiffReconciler, Reconcilers},
+    deribit,
+    recorder::{read_frames, recording_files},
+    MessageStream, Provider, RecordedFrame,
+};
+use crate::core::{fixed_point::InstrumentSpecs, messages::Message};
+use crate::error::Error;
+use crate::utils::config::{
+    normalize_instrument, ExchangeConfig, Feed, Provider as ProviderName, ReplayConfig, ReplaySpeed,
+};
+
+const FRAMES_CAPACITY: usize = 1024;
+
+/// Replay provider.
+///
+/// Plays back the frames written by the `Recorder` through the parsers of the provider which
+/// received them, paced by the receive times. The exchange configuration must match the
+/// recorded feed, e.g. the incremental Bitstamp feed is seeded from its recorded snapshot.
+/// At the end of the recording the book keeps its last state.
+pub struct Replay {
+    config: ExchangeConfig,
+    replay: ReplayConfig,
+    files: Vec<PathBuf>,
+    reconcilers: Reconcilers,
+}
+
+impl Replay {
+    pub fn new(config: ExchangeConfig, replay: ReplayConfig) -> Self {
+        Replay {
+            config,
+            replay,
+            files: vec![],
+            reconcilers: Reconcilers::default(),
+        }
+    }
+}
+
+impl Provider for Replay {
+    fn connect(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        async move {
+            let path = self
+                .replay
+                .path
+                .as_ref()
+                .ok_or_else(|| Error::Config("Replay path is not set".to_string()))?;
+            self.files = recording_files(path.as_ref())?;
+            if self.files.is_empty() {
+                return Err(Error::Config(format!("No recordings found in {}", path)));
+            }
+            self.reconcilers.lock().unwrap().clear();
+
+            info!(
+                "Replaying {} recording files from {}",
+                self.files.len(),
+                path
+            );
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn subscribe<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        async move {
+            if self.config.feed.unwrap_or_default() == Feed::Incremental {
+                self.reconcilers
+                    .lock()
+                    .unwrap()
+                    .insert(normalize_instrument(instrument), DiffReconciler::default());
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn unsubscribe<'a>(&'a mut self, instrument: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        async move {
+            self.reconcilers
+                .lock()
+                .unwrap()
+                .remove(&normalize_instrument(instrument));
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn messages(&mut self) -> Result<MessageStream, Error> {
+        if self.files.is_empty() {
+            return Err(Error::NotConnected("Replay"));
+        }
+
+        // files are read on a blocking thread, the bounded channel keeps the reader ahead
+        let (frames_tx, frames_rx) = mpsc::channel(FRAMES_CAPACITY);
+        let files = std::mem::take(&mut self.files);
+        tokio::task::spawn_blocking(move || {
+            for file in files {
+                let frames = match read_frames(&file) {
+                    Ok(frames) => frames,
+                    Err(e) => {
+                        warn!("Skipped recording {}: {:?}", file.display(), e);
+                        continue;
+                    }
+                };
+                for frame in frames {
+                    let frame = match frame {
+                        Ok(frame) => frame,
+                        Err(e) => {
+                            warn!("Skipped frame of {}: {:?}", file.display(), e);
+                            continue;
+                        }
+                    };
+                    if frames_tx.blocking_send(frame).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        let pacing = Pacing::new(self.replay.speed.unwrap_or_default());
+        let frames = stream::unfold((frames_rx, pacing), |(mut frames, mut pacing)| async move {
+            let Some(frame) = frames.recv().await else {
+                info!("Replay finished");
+                return None;
+            };
+            pacing.wait(frame.received_us).await;
+            Some(((frame, std::time::Instant::now()), (frames, pacing)))
+        });
+
+        let specs = self.config.instrument_specs();
+        let incremental = self.config.feed.unwrap_or_default() == Feed::Incremental;
+        let reconcilers = self.reconcilers.clone();
+        let messages = frames
+            .flat_map(move |(frame, received)| {
+                let messages = match parse(&frame, &specs) {
+                    Some((channel, message)) if is_bitstamp(&frame) => {
+                        if incremental {
+                            bitstamp::reconcile(&reconcilers, channel, message, received).0
+                        } else {
+                            vec![Ok((bitstamp::instrument(&channel), message, received))]
+                        }
+                    }
+                    Some((instrument, message)) => vec![Ok((instrument, message, received))],
+                    None => vec![],
+                };
+                stream::iter(messages)
+            })
+            // the end of a recording is not a lost connection
+            .chain(stream::pending());
+
+        Ok(messages.boxed())
+    }
+}
+
+fn is_bitstamp(frame: &RecordedFrame) -> bool {
+    frame.provider == ProviderName::Bitstamp.name()
+}
+
+/// Parse a recorded frame with the parser of its provider.
+///
+/// Bitstamp frames are tagged with the channel, to be reconciled on the incremental feed,
+/// the others with the normalized instrument.
+fn parse(frame: &RecordedFrame, specs: &InstrumentSpecs) -> Option<(String, Message)> {
+    if is_bitstamp(frame) {
+        return bitstamp::parse(&frame.frame, specs);
+    }
+    if frame.provider == ProviderName::Deribit.name() {
+        return deribit::parse_frame(&frame.frame, specs);
+    }
+
+    warn!("Skipped frame of unsupported provider {}", frame.provider);
+    None
+}
+
+/// Delays the frames by their receive times relative to the first one, scaled by the speed.
+struct Pacing {
+    speed: ReplaySpeed,
+    start: Option<(Instant, u64)>,
+}
+
+impl Pacing {
+    fn new(speed: ReplaySpeed) -> Self {
+        Pacing { speed, start: None }
+    }
+
+    async fn wait(&mut self, received_us: u64) {
+        let ReplaySpeed::Scaled(factor) = self.speed else {
+            return;
+        };
+        let (start, first_us) = *self.start.get_or_insert((Instant::now(), received_us));
+        let offset = Duration::from_micros(received_us.saturating_sub(first_us)).div_f64(factor);
+        time::sleep_until(start + offset).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixed_point::Price;
+    use crate::providers::Recorder;
+    use crate::utils::config::RecorderConfig;
+
+    fn frame(received_us: u64, channel: &str, text: &str) -> RecordedFrame {
+        RecordedFrame {
+            received_us,
+            provider: "bitstamp".to_string(),
+            channel: channel.to_string(),
+            frame: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_recorded_frames() {
+        let specs = InstrumentSpecs::default();
+        let snapshot = frame(
+            1,
+            "order_book_btcusd",
+            r#"{"data":{"microtimestamp":"100","bids":[["64000","1"]],"asks":[["64010","2"]]},"channel":"order_book_btcusd","event":"data"}"#,
+        );
+        let Some((channel, Message::Snapshot(snapshot))) = parse(&snapshot, &specs) else {
+            panic!("expected snapshot");
+        };
+        assert_eq!(channel, "order_book_btcusd");
+        assert_eq!(snapshot.bids[0].price, Price::from_f64(64000.0));
+
+        let deribit = RecordedFrame {
+            provider: "deribit".to_string(),
+            ..frame(
+                2,
+                "book.BTC-PERPETUAL.100ms",
+                r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-PERPETUAL.100ms","data":{"type":"snapshot","timestamp":1554373962454,"instrument_name":"BTC-PERPETUAL","change_id":297217,"bids":[["new",5042.34,30.0]],"asks":[["new",5042.64,40.0]]}}}"#,
+            )
+        };
+        let Some((instrument, Message::Snapshot(_))) = parse(&deribit, &specs) else {
+            panic!("expected snapshot");
+        };
+        assert_eq!(instrument, "btcperpetual");
+    }
+
+    #[tokio::test]
+    async fn test_replay_recording() {
+        let directory = std::env::temp_dir().join(format!(
+            "l2_replay_{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_micros()
+        ));
+        let recorder = Recorder::new(
+            &RecorderConfig {
+                path: Some(directory.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+            "bitstamp",
+        )
+        .unwrap();
+        recorder.record(
+            r#"{"event":"bts:subscription_succeeded","channel":"order_book_btcusd","data":{}}"#,
+        );
+        recorder.record(r#"{"data":{"microtimestamp":"100","bids":[["64000","1"]],"asks":[]},"channel":"order_book_btcusd","event":"data"}"#);
+        drop(recorder);
+
+        let mut replay = Replay::new(
+            ExchangeConfig::default(),
+            ReplayConfig {
+                path: Some(directory.to_string_lossy().to_string()),
+                speed: Some(ReplaySpeed::Max),
+            },
+        );
+        replay.connect().await.unwrap();
+        replay.subscribe("BTC-USD").await.unwrap();
+        let mut messages = replay.messages().unwrap();
+
+        let (instrument, message, _) = messages.next().await.unwrap().unwrap();
+        assert_eq!(instrument, "btcusd");
+        assert!(matches!(message, Message::Snapshot(_)));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scaled_pacing() {
+        let mut pacing = Pacing::new(ReplaySpeed::Scaled(100.0));
+        let start = Instant::now();
+        pacing.wait(1_000_000).await;
+        pacing.wait(3_000_000).await;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let mut pacing = Pacing::new(ReplaySpeed::Max);
+        let start = Instant::now();
+        pacing.wait(1_000_000).await;
+        pacing.wait(3_000_000).await;
+        assert!(start.elapsed() < Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use futures::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use tokio::{net::TcpStream, sync::Mutex};
+use tokio_tungstenite::{
+    connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream,
+};
+
+use crate::error::Error;
+
+pub(super) type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+pub(super) type WsSink = Arc<Mutex<SplitSink<WsStream, Message>>>;
+pub(super) type WsSource = SplitStream<WsStream>;
+
+/// Open a WebSocket connection and split it into the shared sink and the source.
+pub(super) async fn connect(url: &str) -> Result<(WsSink, WsSource), Error> {
+    // default provider (does not work without), may be installed already on reconnect
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+    let (ws_stream, _) = connect_async(url).await.map_err(|e| Error::Connect {
+        url: url.to_string(),
+        source: Box::new(e),
+    })?;
+    let (write, read) = ws_stream.split();
+    Ok((Arc::new(Mutex::new(write)), read))
+}
+
+/// Send a text frame over the shared sink.
+pub(super) async fn send_text(write: &WsSink, text: String) -> Result<(), Error> {
+    write.lock().await.send(Message::Text(text)).await?;
+    Ok(())
+}
+
+/// Liveness check of a connection by WebSocket pings.
+///
+/// The venue answers a ping with a pong, a ping sent while the previous one is unanswered
+/// fails, so a half-open connection is detected within two heartbeats.
+#[derive(Clone, Default)]
+pub(super) struct Pinger {
+    awaiting_pong: Arc<AtomicBool>,
+}
+
+impl Pinger {
+    pub(super) async fn ping(&self, write: &WsSink) -> Result<(), Error> {
+        if self.awaiting_pong.swap(true, Ordering::SeqCst) {
+            return Err(Error::Protocol("No pong to the previous ping".to_string()));
+        }
+        write.lock().await.send(Message::Ping(vec![])).await?;
+        Ok(())
+    }
+
+    /// Called by the source for every pong of the venue.
+    pub(super) fn pong(&self) {
+        self.awaiting_pong.store(false, Ordering::SeqCst);
+    }
+}
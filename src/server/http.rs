@@ -0,0 +1,166 @@
+use std::{collections::BTreeMap, io, net::SocketAddr};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{net::TcpListener, task::JoinHandle};
+
+use super::BookDirectory;
+use crate::core::{analytics, fixed_point::Price, order_book::Level, SharedOrderBook};
+use crate::providers::{FeedHealth, HealthStatus};
+
+/// State shared by the handlers.
+#[derive(Clone)]
+struct ApiState {
+    books: BookDirectory,
+    /// Health of the provider feeds, keyed by the provider name.
+    feeds: Vec<(String, FeedHealth)>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BookQuery {
+    /// Levels per side, the whole book if not set.
+    depth: Option<usize>,
+}
+
+/// Response of `GET /books/{instrument}`.
+#[derive(Serialize, Debug)]
+pub struct BookResponse {
+    pub instrument: String,
+    pub sequence: Option<u64>,
+    /// Levels with quantities, best first.
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+/// Response of `GET /books/{instrument}/top`.
+#[derive(Serialize, Debug)]
+pub struct TopResponse {
+    pub instrument: String,
+    pub best_bid: Option<Price>,
+    pub best_ask: Option<Price>,
+    pub spread: Option<Price>,
+    pub mid: Option<Price>,
+    /// The feed of the book went silent, the prices may be outdated.
+    pub stale: bool,
+}
+
+/// Response of `GET /health`, `degraded` when a provider is not connected.
+#[derive(Serialize, Debug)]
+pub struct HealthResponse {
+    pub status: &'static str,
+    pub providers: BTreeMap<String, HealthStatus>,
+}
+
+/// Error answered as `{"error": message}` with the status code.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// Embedded HTTP server answering queries of the book state and the feed health.
+pub struct HttpServer {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl HttpServer {
+    /// Listen on the address and serve requests until stopped.
+    pub async fn bind(
+        address: &str,
+        books: BookDirectory,
+        feeds: Vec<(String, FeedHealth)>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(address).await?;
+        let local_addr = listener.local_addr()?;
+        info!("Serving the HTTP API on {}", local_addr);
+
+        let router = router(ApiState { books, feeds });
+        let task = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                warn!("HTTP API stopped: {}", e);
+            }
+        });
+
+        Ok(HttpServer { local_addr, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+}
+
+fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/books/:instrument", get(get_book))
+        .route("/books/:instrument/top", get(get_top))
+        .route("/health", get(get_health))
+        .with_state(state)
+}
+
+fn find_book(state: &ApiState, instrument: &str) -> Result<SharedOrderBook, ApiError> {
+    state.books.get(instrument).ok_or_else(|| {
+        ApiError(
+            StatusCode::NOT_FOUND,
+            format!("Unknown instrument: {}", instrument),
+        )
+    })
+}
+
+async fn get_book(
+    State(state): State<ApiState>,
+    Path(instrument): Path<String>,
+    Query(query): Query<BookQuery>,
+) -> Result<Json<BookResponse>, ApiError> {
+    let snapshot = find_book(&state, &instrument)?.snapshot(query.depth).await;
+    Ok(Json(BookResponse {
+        instrument,
+        sequence: snapshot.sequence,
+        bids: snapshot.bids,
+        asks: snapshot.asks,
+    }))
+}
+
+async fn get_top(
+    State(state): State<ApiState>,
+    Path(instrument): Path<String>,
+) -> Result<Json<TopResponse>, ApiError> {
+    let order_book = find_book(&state, &instrument)?;
+    let snapshot = order_book.snapshot(Some(1)).await;
+    Ok(Json(TopResponse {
+        best_bid: snapshot.best_bid,
+        best_ask: snapshot.best_ask,
+        spread: analytics::spread(&snapshot),
+        mid: analytics::mid(&snapshot),
+        stale: order_book.is_stale(),
+        instrument,
+    }))
+}
+
+async fn get_health(State(state): State<ApiState>) -> (StatusCode, Json<HealthResponse>) {
+    let providers = state
+        .feeds
+        .iter()
+        .map(|(name, health)| (name.clone(), health.status()))
+        .collect::<BTreeMap<_, _>>();
+    let (code, status) = if providers.values().all(|status| status.connected) {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+    };
+    (code, Json(HealthResponse { status, providers }))
+}
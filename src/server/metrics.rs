@@ -0,0 +1,61 @@
+use std::{io, net::SocketAddr};
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use log::{info, warn};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+use super::BookDirectory;
+use crate::utils::metrics::metrics;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// HTTP server exporting the metrics on `GET /metrics` for Prometheus to scrape.
+///
+/// Gauges of the books (depth, spread, update age) are taken from the directory on every scrape.
+pub struct MetricsServer {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl MetricsServer {
+    /// Listen on the address and serve scrapes until stopped.
+    pub async fn bind(address: &str, books: BookDirectory) -> io::Result<Self> {
+        let listener = TcpListener::bind(address).await?;
+        let local_addr = listener.local_addr()?;
+        info!("Serving the metrics on {}", local_addr);
+
+        let router = Router::new()
+            .route("/metrics", get(get_metrics))
+            .with_state(books);
+        let task = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                warn!("Metrics server stopped: {}", e);
+            }
+        });
+
+        Ok(MetricsServer { local_addr, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+}
+
+async fn get_metrics(State(books): State<BookDirectory>) -> impl IntoResponse {
+    let mut observed = vec![];
+    for instrument in books.instruments() {
+        let Some(order_book) = books.get(&instrument) else {
+            continue;
+        };
+        let stats = order_book.stats().await;
+        observed.push((instrument, stats, order_book.last_update_age()));
+    }
+
+    // books removed since the last scrape disappear
+    let text = metrics().export_books(&observed);
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], text)
+}
@@ -0,0 +1,84 @@
+pub mod http;
+pub mod metrics;
+pub mod websocket;
+
+pub use http::HttpServer;
+pub use metrics::MetricsServer;
+pub use websocket::WsServer;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::core::SharedOrderBook;
+use crate::utils::config::normalize_instrument;
+
+/// Books served to downstream consumers, looked up by instrument.
+///
+/// Names are normalized, `BTC-USD` and `btcusd` refer to the same book.
+#[derive(Clone, Default)]
+pub struct BookDirectory {
+    books: Arc<RwLock<HashMap<String, (String, SharedOrderBook)>>>,
+}
+
+impl BookDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve the book under the instrument, replacing a book served under the same name.
+    pub fn insert(&self, instrument: &str, order_book: SharedOrderBook) {
+        self.books.write().unwrap().insert(
+            normalize_instrument(instrument),
+            (instrument.to_string(), order_book),
+        );
+    }
+
+    pub fn remove(&self, instrument: &str) -> Option<SharedOrderBook> {
+        self.books
+            .write()
+            .unwrap()
+            .remove(&normalize_instrument(instrument))
+            .map(|(_, order_book)| order_book)
+    }
+
+    pub fn get(&self, instrument: &str) -> Option<SharedOrderBook> {
+        self.books
+            .read()
+            .unwrap()
+            .get(&normalize_instrument(instrument))
+            .map(|(_, order_book)| order_book.clone())
+    }
+
+    /// Instruments as inserted, in name order.
+    pub fn instruments(&self) -> Vec<String> {
+        let mut instruments = self
+            .books
+            .read()
+            .unwrap()
+            .values()
+            .map(|(instrument, _)| instrument.clone())
+            .collect::<Vec<_>>();
+        instruments.sort();
+        instruments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_is_normalized() {
+        let directory = BookDirectory::new();
+        directory.insert("BTC-USD", SharedOrderBook::initialise(10));
+
+        assert!(directory.get("btcusd").is_some());
+        assert!(directory.get("ETH-USD").is_none());
+        assert_eq!(directory.instruments(), vec!["BTC-USD".to_string()]);
+
+        assert!(directory.remove("BtcUsd").is_some());
+        assert!(directory.instruments().is_empty());
+    }
+}
@@ -0,0 +1,325 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::SocketAddr,
+    time::Duration,
+};
+
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Sender},
+    task::JoinHandle,
+    time,
+};
+use tokio_tungstenite::{accept_async, tungstenite::protocol::Message};
+
+use super::BookDirectory;
+use crate::core::{
+    fixed_point::{Price, Quantity},
+    order_book::Level,
+    SharedOrderBook,
+};
+
+/// Pending messages of a client before the feeds of its subscriptions wait.
+const CLIENT_BUFFER: usize = 64;
+
+/// Request of a client.
+///
+/// e.g. `{"op":"subscribe","instrument":"BTC-USD","depth":10,"throttle_ms":100}`
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ClientRequest {
+    Subscribe {
+        instrument: String,
+        /// Levels per side, the whole book if not set.
+        depth: Option<usize>,
+        /// Minimum interval between two deltas, changes in between are merged.
+        throttle_ms: Option<u64>,
+    },
+    Unsubscribe {
+        instrument: String,
+    },
+}
+
+/// Message sent to a client.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Subscribed {
+        instrument: String,
+    },
+    Unsubscribed {
+        instrument: String,
+    },
+    /// Levels of the book, best first, sent once after subscribing.
+    Snapshot {
+        instrument: String,
+        sequence: Option<u64>,
+        bids: Vec<Level>,
+        asks: Vec<Level>,
+    },
+    /// Changed levels since the previous message, a zero quantity removes the level.
+    Delta {
+        instrument: String,
+        sequence: Option<u64>,
+        bids: Vec<Level>,
+        asks: Vec<Level>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Levels of `new` that differ from `old`, levels missing in `new` with a zero quantity.
+///
+/// Both sides are limited to the same depth, so a level leaving the depth is removed.
+pub fn diff_levels(old: &[Level], new: &[Level]) -> Vec<Level> {
+    let old_quantities = old
+        .iter()
+        .map(|level| (level.price, level.quantity))
+        .collect::<HashMap<Price, Quantity>>();
+    let new_prices = new.iter().map(|level| level.price).collect::<HashSet<_>>();
+
+    let removed = old
+        .iter()
+        .filter(|level| !new_prices.contains(&level.price))
+        .map(|level| Level {
+            price: level.price,
+            quantity: Quantity::ZERO,
+        });
+    let changed = new
+        .iter()
+        .filter(|level| old_quantities.get(&level.price) != Some(&level.quantity))
+        .copied();
+    removed.chain(changed).collect()
+}
+
+/// WebSocket server re-broadcasting the books of the directory.
+///
+/// Clients subscribe to instruments and receive a snapshot followed by deltas.
+/// Updates are conflated, a slow client receives the latest state instead of every change.
+pub struct WsServer {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl WsServer {
+    /// Listen on the address and serve clients until stopped.
+    pub async fn bind(address: &str, books: BookDirectory) -> io::Result<Self> {
+        let listener = TcpListener::bind(address).await?;
+        let local_addr = listener.local_addr()?;
+        info!("Serving the books over WebSocket on {}", local_addr);
+
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        tokio::spawn(serve_client(stream, peer, books.clone()));
+                    }
+                    Err(e) => warn!("Failed to accept a WebSocket client: {}", e),
+                }
+            }
+        });
+
+        Ok(WsServer { local_addr, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stop accepting clients, connected clients are served until they disconnect.
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+}
+
+async fn serve_client(stream: TcpStream, peer: SocketAddr, books: BookDirectory) {
+    let ws = match accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            debug!("WebSocket handshake with {} failed: {}", peer, e);
+            return;
+        }
+    };
+    debug!("WebSocket client {} connected", peer);
+
+    let (mut write, mut read) = ws.split();
+    let (tx, mut rx) = mpsc::channel::<String>(CLIENT_BUFFER);
+    let mut feeds: HashMap<String, JoinHandle<()>> = HashMap::new();
+
+    loop {
+        tokio::select! {
+            Some(text) = rx.recv() => {
+                if write.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            message = read.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // pings are answered by the stream
+                    Some(Ok(_)) => continue,
+                };
+                let reply = handle_request(&text, &books, &mut feeds, &tx);
+                if write.send(Message::Text(reply.to_json())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    for feed in feeds.values() {
+        feed.abort();
+    }
+    debug!("WebSocket client {} disconnected", peer);
+}
+
+fn handle_request(
+    text: &str,
+    books: &BookDirectory,
+    feeds: &mut HashMap<String, JoinHandle<()>>,
+    tx: &Sender<String>,
+) -> ServerMessage {
+    let request = match serde_json::from_str::<ClientRequest>(text) {
+        Ok(request) => request,
+        Err(e) => {
+            return ServerMessage::Error {
+                message: format!("Invalid request: {}", e),
+            }
+        }
+    };
+
+    match request {
+        ClientRequest::Subscribe {
+            instrument,
+            depth,
+            throttle_ms,
+        } => {
+            let Some(order_book) = books.get(&instrument) else {
+                return ServerMessage::Error {
+                    message: format!("Unknown instrument: {}", instrument),
+                };
+            };
+            let throttle = Duration::from_millis(throttle_ms.unwrap_or_default());
+            // the snapshot is queued after the acknowledgement sent by the caller
+            let feed = tokio::spawn(feed(
+                instrument.clone(),
+                order_book,
+                depth,
+                throttle,
+                tx.clone(),
+            ));
+            // subscribing again replaces the depth and the throttle
+            if let Some(previous) = feeds.insert(instrument.clone(), feed) {
+                previous.abort();
+            }
+            ServerMessage::Subscribed { instrument }
+        }
+        ClientRequest::Unsubscribe { instrument } => match feeds.remove(&instrument) {
+            Some(feed) => {
+                feed.abort();
+                ServerMessage::Unsubscribed { instrument }
+            }
+            None => ServerMessage::Error {
+                message: format!("Not subscribed: {}", instrument),
+            },
+        },
+    }
+}
+
+/// Send the snapshot of the book, then a delta per change until the client goes away.
+async fn feed(
+    instrument: String,
+    order_book: SharedOrderBook,
+    depth: Option<usize>,
+    throttle: Duration,
+    tx: Sender<String>,
+) {
+    // subscribe before the snapshot so no change is missed
+    let mut subscription = order_book.subscribe(depth);
+    let mut last = order_book.snapshot(depth).await;
+    let snapshot = ServerMessage::Snapshot {
+        instrument: instrument.clone(),
+        sequence: last.sequence,
+        bids: last.bids.clone(),
+        asks: last.asks.clone(),
+    };
+    if tx.send(snapshot.to_json()).await.is_err() {
+        return;
+    }
+
+    loop {
+        let snapshot = subscription.next().await;
+        let bids = diff_levels(&last.bids, &snapshot.bids);
+        let asks = diff_levels(&last.asks, &snapshot.asks);
+        if bids.is_empty() && asks.is_empty() {
+            continue;
+        }
+
+        let delta = ServerMessage::Delta {
+            instrument: instrument.clone(),
+            sequence: snapshot.sequence,
+            bids,
+            asks,
+        };
+        if tx.send(delta.to_json()).await.is_err() {
+            return;
+        }
+        last = snapshot;
+
+        if !throttle.is_zero() {
+            time::sleep(throttle).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: f64, quantity: f64) -> Level {
+        Level {
+            price: Price::from_f64(price),
+            quantity: Quantity::from_f64(quantity),
+        }
+    }
+
+    #[test]
+    fn test_diff_levels() {
+        let old = vec![level(100.0, 1.0), level(99.0, 2.0), level(98.0, 3.0)];
+        let new = vec![level(100.0, 1.0), level(99.0, 5.0), level(97.5, 1.0)];
+
+        assert_eq!(
+            diff_levels(&old, &new),
+            vec![level(98.0, 0.0), level(99.0, 5.0), level(97.5, 1.0)]
+        );
+        assert!(diff_levels(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            serde_json::from_str::<ClientRequest>(
+                r#"{"op":"subscribe","instrument":"BTC-USD","depth":5}"#
+            )
+            .unwrap(),
+            ClientRequest::Subscribe {
+                instrument: "BTC-USD".to_string(),
+                depth: Some(5),
+                throttle_ms: None,
+            }
+        );
+        assert!(serde_json::from_str::<ClientRequest>(r#"{"op":"cancel"}"#).is_err());
+    }
+}
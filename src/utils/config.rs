@@ -1,17 +1,40 @@
+use clap::ArgMatches;
 use envconfig::Envconfig;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt::Display;
 use std::fs;
 use std::str::FromStr;
 
 use crate::cli::commands::get_cli_args;
+use crate::core::fixed_point::{InstrumentSpec, InstrumentSpecs, Price, Quantity};
+use crate::error::Error;
 
 const DEFAULT_CONFIG_FILE: &str = "config.toml";
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
 pub enum Provider {
     None,
     Deribit,
     Bitstamp,
+    /// Frames recorded from a venue, see `providers::Replay`.
+    Replay,
+    /// Venue registered by an embedding application, see `providers::ProviderRegistry`.
+    Custom(String),
+}
+
+impl Provider {
+    /// Name used to look up the provider in the registry.
+    pub fn name(&self) -> String {
+        match self {
+            Provider::None => "none".to_string(),
+            Provider::Deribit => "deribit".to_string(),
+            Provider::Bitstamp => "bitstamp".to_string(),
+            Provider::Replay => "replay".to_string(),
+            Provider::Custom(name) => name.to_lowercase(),
+        }
+    }
 }
 
 impl FromStr for Provider {
@@ -19,8 +42,246 @@ impl FromStr for Provider {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
+            "" => Err(format!("Invalid value for Provider: {}", s)),
+            "none" => Ok(Provider::None),
             "deribit" => Ok(Provider::Deribit),
-            _ => Err(format!("Invalid value for Provider: {}", s)),
+            "bitstamp" => Ok(Provider::Bitstamp),
+            "replay" => Ok(Provider::Replay),
+            _ => Ok(Provider::Custom(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for Provider {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Kind of order book feed to subscribe to.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Feed {
+    /// Periodic snapshots limited by the depth, e.g. Deribit grouped book.
+    #[default]
+    Snapshot,
+    /// Initial snapshot followed by per-level changes, full precision and depth.
+    Incremental,
+}
+
+impl FromStr for Feed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "snapshot" => Ok(Feed::Snapshot),
+            "incremental" => Ok(Feed::Incremental),
+            _ => Err(format!("Invalid value for Feed: {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Feed {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Remediation of a crossed or locked order book.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub enum CrossPolicy {
+    /// Only log and report the event.
+    #[default]
+    Log,
+    /// Request a fresh snapshot from the provider.
+    Resync,
+    /// Drop the crossing levels of both sides.
+    Purge,
+}
+
+impl FromStr for CrossPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "log" => Ok(CrossPolicy::Log),
+            "resync" => Ok(CrossPolicy::Resync),
+            "purge" => Ok(CrossPolicy::Purge),
+            _ => Err(format!("Invalid value for CrossPolicy: {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for CrossPolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Output of the book updates.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub enum OutputFormat {
+    /// Terminal UI, or log lines when `RUST_LOG` is set.
+    #[default]
+    Ui,
+    /// JSON object per update on stdout.
+    Json,
+    /// CSV row per update on stdout, after a header row.
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ui" => Ok(OutputFormat::Ui),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("Invalid value for OutputFormat: {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for OutputFormat {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Playback speed of a replay.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub enum ReplaySpeed {
+    /// As fast as possible, ignoring the receive times.
+    Max,
+    /// Receive times scaled by the factor, `1` is real time and `2` twice as fast.
+    Scaled(f64),
+}
+
+impl Default for ReplaySpeed {
+    fn default() -> Self {
+        ReplaySpeed::Scaled(1.0)
+    }
+}
+
+impl FromStr for ReplaySpeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "max" => Ok(ReplaySpeed::Max),
+            "realtime" => Ok(ReplaySpeed::Scaled(1.0)),
+            factor => match factor.trim_end_matches('x').parse::<f64>() {
+                Ok(factor) if factor > 0.0 => Ok(ReplaySpeed::Scaled(factor)),
+                _ => Err(format!("Invalid value for ReplaySpeed: {}", s)),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for ReplaySpeed {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Value for every instrument or per instrument, e.g. `0.5` or
+/// `BTC-PERPETUAL:0.5,ETH-PERPETUAL:0.05`, in a file also a table of the instruments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerInstrument<T> {
+    /// Value of the instruments not listed.
+    pub default: Option<T>,
+    /// Values by normalized instrument.
+    pub instruments: BTreeMap<String, T>,
+}
+
+impl<T: Copy> PerInstrument<T> {
+    /// Same value for every instrument.
+    pub fn all(value: T) -> Self {
+        PerInstrument {
+            default: Some(value),
+            instruments: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, instrument: &str) -> Option<T> {
+        self.instruments
+            .get(&normalize_instrument(instrument))
+            .copied()
+            .or(self.default)
+    }
+}
+
+impl<T: FromStr> FromStr for PerInstrument<T>
+where
+    T::Err: Display,
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |e: &dyn Display| format!("Invalid per instrument value {}: {}", s, e);
+        let mut value = PerInstrument {
+            default: None,
+            instruments: BTreeMap::new(),
+        };
+        for entry in s.split(',').map(str::trim) {
+            match entry.rsplit_once(':') {
+                Some((instrument, v)) => {
+                    let v = v.parse().map_err(|e| invalid(&e))?;
+                    value
+                        .instruments
+                        .insert(normalize_instrument(instrument.trim()), v);
+                }
+                None if value.default.is_none() => {
+                    value.default = Some(entry.parse().map_err(|e| invalid(&e))?);
+                }
+                None => return Err(invalid(&"more than one default")),
+            }
+        }
+        Ok(value)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for PerInstrument<T>
+where
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw<T> {
+            Value(T),
+            Instruments(BTreeMap<String, T>),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Value(value) => Ok(PerInstrument {
+                default: Some(value),
+                instruments: BTreeMap::new(),
+            }),
+            Raw::Instruments(instruments) => Ok(PerInstrument {
+                default: None,
+                instruments: instruments
+                    .into_iter()
+                    .map(|(instrument, value)| (normalize_instrument(&instrument), value))
+                    .collect(),
+            }),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
         }
     }
 }
@@ -31,79 +292,288 @@ pub struct Config {
     pub exchange: ExchangeConfig,
     #[envconfig(nested = true)]
     pub provider: ProviderConfig,
+    #[serde(default)]
+    #[envconfig(nested = true)]
+    pub reconnect: ReconnectConfig,
+    #[serde(default)]
+    #[envconfig(nested = true)]
+    pub recorder: RecorderConfig,
+    #[serde(default)]
+    #[envconfig(nested = true)]
+    pub replay: ReplayConfig,
+    #[serde(default)]
+    #[envconfig(nested = true)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    #[envconfig(nested = true)]
+    pub server: ServerConfig,
 }
 
-#[derive(Deserialize, Debug, Default, Envconfig, PartialEq)]
+#[derive(Deserialize, Debug, Default, Envconfig, Clone, PartialEq)]
 pub struct ExchangeConfig {
     #[envconfig(from = "EXCHANGE_DEPTH_LIMIT")]
     pub depth_limit: Option<usize>,
     #[envconfig(from = "EXCHANGE_INSTRUMENT")]
     pub instrument: Option<String>,
+    #[envconfig(from = "EXCHANGE_FEED")]
+    pub feed: Option<Feed>,
+    /// Update interval of the provider channel, e.g. `100ms` or `raw` for Deribit.
+    #[envconfig(from = "EXCHANGE_INTERVAL")]
+    pub interval: Option<String>,
+    /// Price increment per instrument, prices off it are rejected.
+    #[envconfig(from = "EXCHANGE_TICK_SIZE")]
+    pub tick_size: Option<PerInstrument<Price>>,
+    /// Quantity increment per instrument, quantities off it are rejected.
+    #[envconfig(from = "EXCHANGE_LOT_SIZE")]
+    pub lot_size: Option<PerInstrument<Quantity>>,
+    /// Remediation of a crossed or locked book, `log` by default.
+    #[envconfig(from = "EXCHANGE_ON_CROSS")]
+    pub on_cross: Option<CrossPolicy>,
+    /// WebSocket endpoint of the provider, the production endpoint of the venue if not set.
+    #[envconfig(from = "EXCHANGE_URL")]
+    pub url: Option<String>,
+    /// Reconnect when an instrument gets no message for this long, in milliseconds, off if not set.
+    #[envconfig(from = "EXCHANGE_STALE_AFTER_MS")]
+    pub stale_after_ms: Option<u64>,
+    /// Interval of the connection heartbeat (WebSocket ping, Deribit `set_heartbeat`), in seconds.
+    #[envconfig(from = "EXCHANGE_HEARTBEAT_SECS")]
+    pub heartbeat_secs: Option<u64>,
+    /// Stream the public trades of the instrument next to the book, off if not set.
+    #[envconfig(from = "EXCHANGE_TRADES")]
+    pub trades: Option<bool>,
 }
 
 impl ExchangeConfig {
-    pub fn normalized_instrument(&self) -> Option<String> {
+    /// Levels kept per side of the book. Incremental feeds keep every level, as the venue
+    /// only sends changes and a trimmed level would not come back.
+    pub fn book_depth_limit(&self) -> Option<usize> {
+        match self.feed.unwrap_or_default() {
+            Feed::Snapshot => self.depth_limit,
+            Feed::Incremental => None,
+        }
+    }
+
+    /// Price and quantity grid of the instrument, the finest grid for missing values.
+    pub fn instrument_spec(&self, instrument: &str) -> InstrumentSpec {
+        let default = InstrumentSpec::default();
+        InstrumentSpec::new(
+            self.tick_size
+                .as_ref()
+                .and_then(|tick_size| tick_size.get(instrument))
+                .unwrap_or(default.tick_size),
+            self.lot_size
+                .as_ref()
+                .and_then(|lot_size| lot_size.get(instrument))
+                .unwrap_or(default.lot_size),
+        )
+    }
+
+    /// Grids of all instruments with a configured tick or lot size, by normalized instrument.
+    pub fn instrument_specs(&self) -> InstrumentSpecs {
+        let mut specs = InstrumentSpecs::new(self.instrument_spec(""));
+        let tick_instruments = self.tick_size.iter().flat_map(|v| v.instruments.keys());
+        let lot_instruments = self.lot_size.iter().flat_map(|v| v.instruments.keys());
+        for instrument in tick_instruments.chain(lot_instruments) {
+            specs.insert(instrument.clone(), self.instrument_spec(instrument));
+        }
+        specs
+    }
+
+    /// Instruments of the comma separated `instrument` list, e.g. `BTC-USD,ETH-USD`.
+    pub fn instruments(&self) -> Vec<String> {
         self.instrument
-            .as_ref()
-            .map(|instrument| instrument.to_lowercase().replace('-', "").to_string())
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|instrument| !instrument.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    pub fn normalized_instrument(&self) -> Option<String> {
+        self.instrument.as_deref().map(normalize_instrument)
     }
 }
 
+/// Normalize an instrument name to the lowercase form without dashes, e.g. `BTC-USD` -> `btcusd`.
+pub fn normalize_instrument(instrument: &str) -> String {
+    instrument.to_lowercase().replace('-', "")
+}
+
 #[derive(Deserialize, Debug, Default, Envconfig, PartialEq)]
 pub struct ProviderConfig {
     #[envconfig(from = "PROVIDER_NAME")]
     pub name: Option<Provider>,
 }
 
+/// Exponential backoff of the provider reconnection, defaults are used for missing values.
+#[derive(Deserialize, Debug, Default, Envconfig, Clone, PartialEq)]
+pub struct ReconnectConfig {
+    /// Delay before the first reconnect attempt, in milliseconds.
+    #[envconfig(from = "RECONNECT_INITIAL_DELAY_MS")]
+    pub initial_delay_ms: Option<u64>,
+    /// Upper bound of the delay, in milliseconds.
+    #[envconfig(from = "RECONNECT_MAX_DELAY_MS")]
+    pub max_delay_ms: Option<u64>,
+    /// Growth factor of the delay after every failed attempt.
+    #[envconfig(from = "RECONNECT_MULTIPLIER")]
+    pub multiplier: Option<f64>,
+    /// Random spread of the delay as a fraction, e.g. `0.2` for +-20%.
+    #[envconfig(from = "RECONNECT_JITTER")]
+    pub jitter: Option<f64>,
+    /// Consecutive failed attempts before giving up, unlimited if not set.
+    #[envconfig(from = "RECONNECT_MAX_ATTEMPTS")]
+    pub max_attempts: Option<u32>,
+}
+
+/// Recording of the raw provider frames, disabled when the path is not set.
+#[derive(Deserialize, Debug, Default, Envconfig, Clone, PartialEq)]
+pub struct RecorderConfig {
+    /// Directory of the recording files, created if missing.
+    #[envconfig(from = "RECORDER_PATH")]
+    pub path: Option<String>,
+    /// Size of a file before a new one is started, in bytes of uncompressed frames.
+    #[envconfig(from = "RECORDER_MAX_FILE_BYTES")]
+    pub max_file_bytes: Option<u64>,
+    /// Age of a file before a new one is started, in seconds.
+    #[envconfig(from = "RECORDER_MAX_FILE_SECS")]
+    pub max_file_secs: Option<u64>,
+    /// Compress the files with zstd.
+    #[envconfig(from = "RECORDER_COMPRESS")]
+    pub compress: Option<bool>,
+}
+
+impl RecorderConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+}
+
+/// Playback of recorded frames by the `Replay` provider.
+#[derive(Deserialize, Debug, Default, Envconfig, Clone, PartialEq)]
+pub struct ReplayConfig {
+    /// Recording file, or directory of recording files replayed in name order.
+    #[envconfig(from = "REPLAY_PATH")]
+    pub path: Option<String>,
+    /// `max`, `realtime` or a speed factor such as `10x`, real time by default.
+    #[envconfig(from = "REPLAY_SPEED")]
+    pub speed: Option<ReplaySpeed>,
+}
+
+/// Output of the book updates, the terminal UI by default.
+#[derive(Deserialize, Debug, Default, Envconfig, Clone, PartialEq)]
+pub struct OutputConfig {
+    /// `ui`, `json` or `csv`.
+    #[envconfig(from = "OUTPUT_FORMAT")]
+    pub format: Option<OutputFormat>,
+    /// Levels per side in the streamed records, 5 by default.
+    #[envconfig(from = "OUTPUT_LEVELS")]
+    pub levels: Option<usize>,
+}
+
+impl OutputConfig {
+    /// Whether the updates are streamed to stdout instead of the terminal UI.
+    pub fn is_headless(&self) -> bool {
+        self.format.unwrap_or_default() != OutputFormat::Ui
+    }
+}
+
+/// Servers exposing the books to downstream consumers, each disabled when its address is not set.
+#[derive(Deserialize, Debug, Default, Envconfig, Clone, PartialEq)]
+pub struct ServerConfig {
+    /// Address of the WebSocket re-broadcast server, e.g. `127.0.0.1:9001`.
+    #[envconfig(from = "SERVER_WS_ADDRESS")]
+    pub ws_address: Option<String>,
+    /// Address of the HTTP API, e.g. `127.0.0.1:8080`.
+    #[envconfig(from = "SERVER_HTTP_ADDRESS")]
+    pub http_address: Option<String>,
+    /// Address of the Prometheus metrics endpoint `/metrics`, e.g. `127.0.0.1:9100`.
+    #[envconfig(from = "SERVER_METRICS_ADDRESS")]
+    pub metrics_address: Option<String>,
+}
+
 impl Config {
     /// Read the configuration from different sources and merge them.
-    pub fn read_config() -> Self {
+    pub fn read_config() -> Result<Self, Error> {
         // Order is important: environment variables > toml file > command line arguments
-        let mut config = Self::from_toml_file(DEFAULT_CONFIG_FILE);
+        let mut config = Self::from_toml_file(DEFAULT_CONFIG_FILE)?;
 
-        let env_config = Self::read_env();
+        let env_config = Self::read_env()?;
         config.merge(env_config);
 
-        let cli_config = Self::from_cli_args();
+        let cli_config = Self::from_cli_args()?;
         config.merge(cli_config);
 
-        config
+        Ok(config)
     }
 
-    fn read_env() -> Self {
-        Config::init_from_env().expect("Unable to read environment variables")
+    fn read_env() -> Result<Self, Error> {
+        Config::init_from_env()
+            .map_err(|e| Error::Config(format!("Unable to read environment variables: {}", e)))
     }
 
-    fn from_toml_file(file: &str) -> Self {
+    fn from_toml_file(file: &str) -> Result<Self, Error> {
         let content = fs::read_to_string(file);
         match content {
-            Ok(content) => toml::from_str(&content).expect("Unable to parse config file"),
+            Ok(content) => toml::from_str(&content)
+                .map_err(|e| Error::Config(format!("Unable to parse config file: {}", e))),
             Err(_) => {
                 eprintln!("Config file '{}' not found, using default values.", file);
-                Self::default()
+                Ok(Self::default())
             }
         }
     }
 
-    fn from_cli_args() -> Self {
+    fn from_cli_args() -> Result<Self, Error> {
         let matches = get_cli_args();
 
         // Extract command-line arguments
         let depth_limit = matches.get_one::<usize>("depth_limit").cloned();
         let instrument = matches.get_one::<String>("instrument").cloned();
-        let provider_name = matches
-            .get_one::<String>("provider")
-            .and_then(|s| s.parse().ok());
-
-        Config {
+        let feed = parse_arg(&matches, "feed")?;
+        let trades = matches.get_flag("trades").then_some(true);
+        let replay = matches.get_one::<String>("replay").cloned();
+        let provider_name =
+            parse_arg(&matches, "provider")?.or_else(|| replay.as_ref().map(|_| Provider::Replay));
+        let record = matches.get_one::<String>("record").cloned();
+        let speed = parse_arg(&matches, "speed")?;
+        let output_format = parse_arg(&matches, "output")?;
+        let output_levels = matches.get_one::<usize>("levels").cloned();
+        let ws_address = matches.get_one::<String>("ws").cloned();
+        let http_address = matches.get_one::<String>("http").cloned();
+        let metrics_address = matches.get_one::<String>("metrics").cloned();
+
+        Ok(Config {
             exchange: ExchangeConfig {
                 depth_limit,
                 instrument,
+                feed,
+                trades,
+                ..Default::default()
             },
             provider: ProviderConfig {
                 name: provider_name,
             },
-        }
+            reconnect: ReconnectConfig::default(),
+            recorder: RecorderConfig {
+                path: record,
+                ..Default::default()
+            },
+            replay: ReplayConfig {
+                path: replay,
+                speed,
+            },
+            output: OutputConfig {
+                format: output_format,
+                levels: output_levels,
+            },
+            server: ServerConfig {
+                ws_address,
+                http_address,
+                metrics_address,
+            },
+        })
     }
 
     /// Merge another Config into self, giving priority to non-None values of the other Config.
@@ -114,28 +584,136 @@ impl Config {
         if let Some(instrument) = other.exchange.instrument {
             self.exchange.instrument = Some(instrument);
         }
+        if let Some(feed) = other.exchange.feed {
+            self.exchange.feed = Some(feed);
+        }
+        if let Some(interval) = other.exchange.interval {
+            self.exchange.interval = Some(interval);
+        }
+        if let Some(tick_size) = other.exchange.tick_size {
+            self.exchange.tick_size = Some(tick_size);
+        }
+        if let Some(lot_size) = other.exchange.lot_size {
+            self.exchange.lot_size = Some(lot_size);
+        }
+        if let Some(on_cross) = other.exchange.on_cross {
+            self.exchange.on_cross = Some(on_cross);
+        }
+        if let Some(url) = other.exchange.url {
+            self.exchange.url = Some(url);
+        }
+        if let Some(stale_after_ms) = other.exchange.stale_after_ms {
+            self.exchange.stale_after_ms = Some(stale_after_ms);
+        }
+        if let Some(heartbeat_secs) = other.exchange.heartbeat_secs {
+            self.exchange.heartbeat_secs = Some(heartbeat_secs);
+        }
+        if let Some(trades) = other.exchange.trades {
+            self.exchange.trades = Some(trades);
+        }
         if let Some(name) = other.provider.name {
             self.provider.name = Some(name);
         }
+        if let Some(initial_delay_ms) = other.reconnect.initial_delay_ms {
+            self.reconnect.initial_delay_ms = Some(initial_delay_ms);
+        }
+        if let Some(max_delay_ms) = other.reconnect.max_delay_ms {
+            self.reconnect.max_delay_ms = Some(max_delay_ms);
+        }
+        if let Some(multiplier) = other.reconnect.multiplier {
+            self.reconnect.multiplier = Some(multiplier);
+        }
+        if let Some(jitter) = other.reconnect.jitter {
+            self.reconnect.jitter = Some(jitter);
+        }
+        if let Some(max_attempts) = other.reconnect.max_attempts {
+            self.reconnect.max_attempts = Some(max_attempts);
+        }
+        if let Some(path) = other.recorder.path {
+            self.recorder.path = Some(path);
+        }
+        if let Some(max_file_bytes) = other.recorder.max_file_bytes {
+            self.recorder.max_file_bytes = Some(max_file_bytes);
+        }
+        if let Some(max_file_secs) = other.recorder.max_file_secs {
+            self.recorder.max_file_secs = Some(max_file_secs);
+        }
+        if let Some(compress) = other.recorder.compress {
+            self.recorder.compress = Some(compress);
+        }
+        if let Some(path) = other.replay.path {
+            self.replay.path = Some(path);
+        }
+        if let Some(speed) = other.replay.speed {
+            self.replay.speed = Some(speed);
+        }
+        if let Some(format) = other.output.format {
+            self.output.format = Some(format);
+        }
+        if let Some(levels) = other.output.levels {
+            self.output.levels = Some(levels);
+        }
+        if let Some(ws_address) = other.server.ws_address {
+            self.server.ws_address = Some(ws_address);
+        }
+        if let Some(http_address) = other.server.http_address {
+            self.server.http_address = Some(http_address);
+        }
+        if let Some(metrics_address) = other.server.metrics_address {
+            self.server.metrics_address = Some(metrics_address);
+        }
     }
 
-    /// Validate that all necessary configuration fields are present and throw an error if any are invalid.
-    pub fn validate(&self) {
-        if self.exchange.instrument.is_none() {
-            panic!("Instrument not specified in the configuration!");
+    /// Validate that all necessary configuration fields are present and valid.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: &str| Err(Error::Config(message.to_string()));
+        if self.exchange.instruments().is_empty() {
+            return invalid("Instrument not specified in the configuration!");
+        }
+        match self.exchange.feed.unwrap_or_default() {
+            Feed::Snapshot => {
+                let Some(depth_limit) = self.exchange.depth_limit else {
+                    return invalid("Depth limit not specified in the configuration!");
+                };
+                if !matches!(depth_limit, 1 | 10 | 20) {
+                    return invalid(
+                        "Depth limit must be one of the following values: 1, 10, or 20!",
+                    );
+                }
+            }
+            Feed::Incremental => {
+                if self.exchange.depth_limit == Some(0) {
+                    return invalid("Depth limit must be greater than 0!");
+                }
+            }
         }
-        if self.exchange.depth_limit.is_none() {
-            panic!("Depth limit not specified in the configuration!");
+        if self.exchange.stale_after_ms == Some(0) {
+            return invalid("Stale threshold must be greater than 0!");
         }
-        if !matches!(self.exchange.depth_limit.unwrap(), 1 | 10 | 20) {
-            panic!("Depth limit must be one of the following values: 1, 10, or 20!");
+        if self.exchange.heartbeat_secs == Some(0) {
+            return invalid("Heartbeat interval must be greater than 0!");
         }
         if self.provider.name.is_none() {
-            panic!("Provider type not specified in the configuration!");
+            return invalid("Provider type not specified in the configuration!");
         }
+        if self.provider.name == Some(Provider::Replay) && self.replay.path.is_none() {
+            return invalid("Replay path not specified in the configuration!");
+        }
+        Ok(())
     }
 }
 
+/// Value of the command-line argument, an error if it is set but invalid.
+fn parse_arg<T: FromStr<Err = String>>(
+    matches: &ArgMatches,
+    name: &str,
+) -> Result<Option<T>, Error> {
+    matches
+        .get_one::<String>(name)
+        .map(|value| value.parse().map_err(Error::Config))
+        .transpose()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -147,6 +725,13 @@ mod tests {
         env::set_var("PROVIDER_NAME", "deribit");
     }
 
+    fn validation_error(config: &Config) -> String {
+        match config.validate() {
+            Err(Error::Config(message)) => message,
+            other => panic!("expected a configuration error, got {:?}", other),
+        }
+    }
+
     fn teardown_test_env() {
         env::remove_var("EXCHANGE_DEPTH_LIMIT");
         env::remove_var("EXCHANGE_INSTRUMENT");
@@ -156,38 +741,104 @@ mod tests {
     #[test]
     fn test_from_env() {
         setup_test_env();
-        let config = Config::read_env();
+        let config = Config::read_env().unwrap();
         let expected = Config {
             exchange: ExchangeConfig {
                 depth_limit: Some(10),
                 instrument: Some("BTC-USD".to_string()),
+                ..Default::default()
             },
             provider: ProviderConfig {
                 name: Some(Provider::Deribit),
             },
+            ..Default::default()
         };
         assert_eq!(config, expected);
         teardown_test_env();
     }
 
+    #[test]
+    fn test_provider_from_str() {
+        assert_eq!("Bitstamp".parse::<Provider>(), Ok(Provider::Bitstamp));
+        assert_eq!("DERIBIT".parse::<Provider>(), Ok(Provider::Deribit));
+        assert_eq!(
+            "Kraken".parse::<Provider>(),
+            Ok(Provider::Custom("Kraken".to_string()))
+        );
+        assert!("".parse::<Provider>().is_err());
+    }
+
+    #[test]
+    fn test_replay_speed_from_str() {
+        assert_eq!("max".parse::<ReplaySpeed>(), Ok(ReplaySpeed::Max));
+        assert_eq!(
+            "RealTime".parse::<ReplaySpeed>(),
+            Ok(ReplaySpeed::Scaled(1.0))
+        );
+        assert_eq!("10x".parse::<ReplaySpeed>(), Ok(ReplaySpeed::Scaled(10.0)));
+        assert_eq!("0.5".parse::<ReplaySpeed>(), Ok(ReplaySpeed::Scaled(0.5)));
+        assert!("0x".parse::<ReplaySpeed>().is_err());
+        assert!("fast".parse::<ReplaySpeed>().is_err());
+    }
+
+    #[test]
+    fn test_instrument_specs() {
+        let config = ExchangeConfig {
+            tick_size: Some("BTC-PERPETUAL:0.5, ETH-PERPETUAL:0.05".parse().unwrap()),
+            lot_size: Some("10".parse().unwrap()),
+            ..Default::default()
+        };
+        let specs = config.instrument_specs();
+        let spec = |tick_size: &str, lot_size: &str| {
+            InstrumentSpec::new(tick_size.parse().unwrap(), lot_size.parse().unwrap())
+        };
+        assert_eq!(specs.get("btcperpetual"), &spec("0.5", "10"));
+        assert_eq!(specs.get("ethperpetual"), &spec("0.05", "10"));
+        assert_eq!(specs.get("solusdc"), &spec("0.00000001", "10"));
+
+        assert!("0.5,0.1".parse::<PerInstrument<Price>>().is_err());
+        assert!("BTC-PERPETUAL:x".parse::<PerInstrument<Price>>().is_err());
+
+        // a file takes a value for every instrument or a table of them
+        #[derive(Deserialize)]
+        struct File {
+            tick_size: PerInstrument<Price>,
+            lot_size: PerInstrument<Quantity>,
+        }
+        let file: File = toml::from_str(
+            "tick_size = { BTC-PERPETUAL = 0.5, ETH-PERPETUAL = \"0.05\" }\nlot_size = 1",
+        )
+        .unwrap();
+        assert_eq!(
+            file.tick_size.get("ETH-PERPETUAL"),
+            Some("0.05".parse().unwrap())
+        );
+        assert_eq!(file.tick_size.get("BTC-USD"), None);
+        assert_eq!(file.lot_size, PerInstrument::all("1".parse().unwrap()));
+    }
+
     #[test]
     fn test_merge_configs() {
         let mut file_config = Config {
             exchange: ExchangeConfig {
                 depth_limit: Some(5),
                 instrument: Some("BTC-ETH".to_string()),
+                ..Default::default()
             },
             provider: ProviderConfig {
                 name: Some(Provider::Deribit),
             },
+            ..Default::default()
         };
 
         let env_config = Config {
             exchange: ExchangeConfig {
                 depth_limit: Some(10),
                 instrument: Some("BTC-USD".to_string()),
+                ..Default::default()
             },
             provider: ProviderConfig { name: None },
+            ..Default::default()
         };
 
         file_config.merge(env_config);
@@ -196,70 +847,112 @@ mod tests {
             exchange: ExchangeConfig {
                 depth_limit: Some(10),
                 instrument: Some("BTC-USD".to_string()),
+                ..Default::default()
             },
             provider: ProviderConfig {
                 name: Some(Provider::Deribit),
             },
+            ..Default::default()
         };
 
         assert_eq!(file_config, expected);
     }
 
     #[test]
-    #[should_panic(expected = "Instrument not specified in the configuration!")]
     fn test_validate_missing_instrument() {
         let config = Config {
             exchange: ExchangeConfig {
                 depth_limit: Some(10),
                 instrument: None,
+                ..Default::default()
             },
             provider: ProviderConfig {
                 name: Some(Provider::Deribit),
             },
+            ..Default::default()
         };
-        config.validate();
+        assert_eq!(
+            validation_error(&config),
+            "Instrument not specified in the configuration!"
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Depth limit not specified in the configuration!")]
     fn test_validate_missing_depth_limit() {
         let config = Config {
             exchange: ExchangeConfig {
                 depth_limit: None,
                 instrument: Some("BTC-USD".to_string()),
+                ..Default::default()
             },
             provider: ProviderConfig {
                 name: Some(Provider::Deribit),
             },
+            ..Default::default()
         };
-        config.validate();
+        assert_eq!(
+            validation_error(&config),
+            "Depth limit not specified in the configuration!"
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Provider type not specified in the configuration!")]
     fn test_validate_missing_provider() {
         let config = Config {
             exchange: ExchangeConfig {
                 depth_limit: Some(10),
                 instrument: Some("BTC-USD".to_string()),
+                ..Default::default()
             },
             provider: ProviderConfig { name: None },
+            ..Default::default()
+        };
+        assert_eq!(
+            validation_error(&config),
+            "Provider type not specified in the configuration!"
+        );
+    }
+
+    #[test]
+    fn test_validate_incremental_depth_limit() {
+        let config = Config {
+            exchange: ExchangeConfig {
+                depth_limit: Some(500),
+                instrument: Some("BTC-PERPETUAL".to_string()),
+                feed: Some(Feed::Incremental),
+                ..Default::default()
+            },
+            provider: ProviderConfig {
+                name: Some(Provider::Deribit),
+            },
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        // the book keeps every level of an incremental feed
+        assert_eq!(config.exchange.book_depth_limit(), None);
+        let snapshot = ExchangeConfig {
+            feed: Some(Feed::Snapshot),
+            ..config.exchange
         };
-        config.validate();
+        assert_eq!(snapshot.book_depth_limit(), Some(500));
     }
 
     #[test]
-    #[should_panic(expected = "Depth limit must be one of the following values: 1, 10, or 20!")]
     fn test_validate_invalid_depth_limit() {
         let config = Config {
             exchange: ExchangeConfig {
                 depth_limit: Some(5),
                 instrument: Some("BTC-USD".to_string()),
+                ..Default::default()
             },
             provider: ProviderConfig {
                 name: Some(Provider::Deribit),
             },
+            ..Default::default()
         };
-        config.validate();
+        assert_eq!(
+            validation_error(&config),
+            "Depth limit must be one of the following values: 1, 10, or 20!"
+        );
     }
 }
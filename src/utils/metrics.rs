@@ -0,0 +1,225 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use prometheus::{
+    exponential_buckets, Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::core::order_book::BookStats;
+
+/// Channel label of frames which could not be read far enough to tell their channel.
+pub const UNKNOWN_CHANNEL: &str = "unknown";
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Metrics of the process, shared by the providers and the order books.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Feed and book health metrics, exported in the Prometheus text format.
+pub struct Metrics {
+    registry: Registry,
+    messages_received: IntCounterVec,
+    parse_failures: IntCounterVec,
+    reconnects: IntCounterVec,
+    apply_latency: HistogramVec,
+    book_depth: IntGaugeVec,
+    book_spread: GaugeVec,
+    book_update_age: GaugeVec,
+    /// Serializes the refreshes of the book gauges with the export of them.
+    books_refresh: Mutex<()>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let messages_received = IntCounterVec::new(
+            Opts::new(
+                "l2_messages_received_total",
+                "Book messages received from the venue",
+            ),
+            &["provider", "channel"],
+        )
+        .unwrap();
+        let parse_failures = IntCounterVec::new(
+            Opts::new(
+                "l2_parse_failures_total",
+                "Frames of the venue skipped as malformed",
+            ),
+            &["provider", "channel"],
+        )
+        .unwrap();
+        let reconnects = IntCounterVec::new(
+            Opts::new("l2_reconnects_total", "Reconnect attempts of the provider"),
+            &["provider"],
+        )
+        .unwrap();
+        // from 10us to about 1.3s
+        let apply_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "l2_apply_latency_seconds",
+                "Time from a frame read from the connection to its message applied to the book",
+            )
+            .buckets(exponential_buckets(0.00001, 2.0, 18).unwrap()),
+            &["provider"],
+        )
+        .unwrap();
+        let book_depth = IntGaugeVec::new(
+            Opts::new("l2_book_depth", "Levels of the book side"),
+            &["instrument", "side"],
+        )
+        .unwrap();
+        let book_spread = GaugeVec::new(
+            Opts::new("l2_book_spread", "Best ask minus best bid"),
+            &["instrument"],
+        )
+        .unwrap();
+        let book_update_age = GaugeVec::new(
+            Opts::new(
+                "l2_book_update_age_seconds",
+                "Time since the last change of the book",
+            ),
+            &["instrument"],
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(messages_received.clone()))
+            .unwrap();
+        registry.register(Box::new(parse_failures.clone())).unwrap();
+        registry.register(Box::new(reconnects.clone())).unwrap();
+        registry.register(Box::new(apply_latency.clone())).unwrap();
+        registry.register(Box::new(book_depth.clone())).unwrap();
+        registry.register(Box::new(book_spread.clone())).unwrap();
+        registry
+            .register(Box::new(book_update_age.clone()))
+            .unwrap();
+
+        Metrics {
+            registry,
+            messages_received,
+            parse_failures,
+            reconnects,
+            apply_latency,
+            book_depth,
+            book_spread,
+            book_update_age,
+            books_refresh: Mutex::default(),
+        }
+    }
+
+    pub fn message_received(&self, provider: &str, channel: &str) {
+        self.messages_received
+            .with_label_values(&[provider, channel])
+            .inc();
+    }
+
+    pub fn parse_failure(&self, provider: &str, channel: &str) {
+        self.parse_failures
+            .with_label_values(&[provider, channel])
+            .inc();
+    }
+
+    pub fn reconnect(&self, provider: &str) {
+        self.reconnects.with_label_values(&[provider]).inc();
+    }
+
+    pub fn applied(&self, provider: &str, latency: Duration) {
+        self.apply_latency
+            .with_label_values(&[provider])
+            .observe(latency.as_secs_f64());
+    }
+
+    /// Set the gauges of the book, usually right before an export.
+    pub fn observe_book(&self, instrument: &str, stats: &BookStats, update_age: Option<Duration>) {
+        self.book_depth
+            .with_label_values(&[instrument, "bid"])
+            .set(stats.bid_depth as i64);
+        self.book_depth
+            .with_label_values(&[instrument, "ask"])
+            .set(stats.ask_depth as i64);
+        if let Some(spread) = stats.spread() {
+            self.book_spread
+                .with_label_values(&[instrument])
+                .set(spread.to_f64());
+        }
+        if let Some(age) = update_age {
+            self.book_update_age
+                .with_label_values(&[instrument])
+                .set(age.as_secs_f64());
+        }
+    }
+
+    /// Drop the gauges of all books, e.g. before observing the books served at the moment.
+    pub fn clear_books(&self) {
+        self.book_depth.reset();
+        self.book_spread.reset();
+        self.book_update_age.reset();
+    }
+
+    /// Replace the gauges of all books with the observed ones and export all metrics.
+    ///
+    /// Concurrent scrapes are serialized, none exports the gauges half cleared by another.
+    pub fn export_books(&self, books: &[(String, BookStats, Option<Duration>)]) -> String {
+        let _refresh = self.books_refresh.lock().unwrap_or_else(|e| e.into_inner());
+        self.clear_books();
+        for (instrument, stats, update_age) in books {
+            self.observe_book(instrument, stats, *update_age);
+        }
+        self.export()
+    }
+
+    /// All metrics in the Prometheus text exposition format.
+    pub fn export(&self) -> String {
+        let mut buffer = vec![];
+        // writing to a vector does not fail
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixed_point::Price;
+
+    #[test]
+    fn test_export() {
+        let metrics = Metrics::new();
+        metrics.message_received("bitstamp", "order_book_btcusd");
+        metrics.parse_failure("bitstamp", UNKNOWN_CHANNEL);
+        metrics.applied("bitstamp", Duration::from_micros(50));
+
+        let stats = BookStats {
+            bid_depth: 2,
+            ask_depth: 1,
+            best_bid: Some(Price::from_f64(100.0)),
+            best_ask: Some(Price::from_f64(100.5)),
+        };
+        metrics.observe_book("BTC-USD", &stats, Some(Duration::from_secs(2)));
+
+        let text = metrics.export();
+        for line in [
+            r#"l2_messages_received_total{channel="order_book_btcusd",provider="bitstamp"} 1"#,
+            r#"l2_parse_failures_total{channel="unknown",provider="bitstamp"} 1"#,
+            r#"l2_apply_latency_seconds_count{provider="bitstamp"} 1"#,
+            r#"l2_book_depth{instrument="BTC-USD",side="bid"} 2"#,
+            r#"l2_book_spread{instrument="BTC-USD"} 0.5"#,
+            r#"l2_book_update_age_seconds{instrument="BTC-USD"} 2"#,
+        ] {
+            assert!(text.contains(line), "missing {} in\n{}", line, text);
+        }
+
+        metrics.clear_books();
+        assert!(!metrics.export().contains("l2_book_depth{"));
+
+        // books removed since the last export disappear
+        let text = metrics.export_books(&[("ETH-USD".to_string(), stats, None)]);
+        assert!(text.contains(r#"l2_book_depth{instrument="ETH-USD",side="bid"} 2"#));
+        assert!(!text.contains(r#"instrument="BTC-USD""#));
+    }
+}
@@ -0,0 +1,226 @@
+//! Local mock exchange speaking the Bitstamp and Deribit WebSocket protocols.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::{net::TcpListener, sync::Notify, time};
+use tokio_tungstenite::{accept_async, tungstenite::protocol::Message};
+
+/// Protocol of the mocked venue.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// `bts:subscribe` / `bts:unsubscribe` events.
+    Bitstamp,
+    /// JSON-RPC `public/subscribe` / `public/unsubscribe` requests.
+    Deribit,
+}
+
+/// Scripted action of a connection, played once the first subscription is acknowledged.
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Send a text frame, valid or not.
+    Send(String),
+    /// Pause before the next step.
+    Sleep(Duration),
+    /// Close the connection.
+    Disconnect,
+}
+
+/// Answer of the mock to the subscription of a channel, Deribit only.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Refusal {
+    /// Reply with a JSON-RPC error.
+    Reject,
+    /// Close the connection without a reply.
+    HangUp,
+}
+
+/// Request received by the mock, e.g. `("subscribe", "order_book_btcusd")`.
+pub type Request = (String, String);
+
+/// Mock exchange accepting connections on a local port.
+///
+/// Every connection plays the next script of the list, connections beyond the scripts
+/// only acknowledge the requests.
+pub struct MockExchange {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<Request>>>,
+    requested: Arc<Notify>,
+    refused: Arc<Mutex<HashMap<String, Refusal>>>,
+}
+
+impl MockExchange {
+    pub async fn start(protocol: Protocol, scripts: Vec<Vec<Step>>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let requested = Arc::new(Notify::new());
+        let refused = Arc::new(Mutex::new(HashMap::new()));
+
+        let mut scripts = VecDeque::from(scripts);
+        let (server_requests, server_requested, server_refused) =
+            (requests.clone(), requested.clone(), refused.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let script = scripts.pop_front().unwrap_or_default();
+                let (requests, requested, refused) = (
+                    server_requests.clone(),
+                    server_requested.clone(),
+                    server_refused.clone(),
+                );
+                tokio::spawn(async move {
+                    if let Ok(ws) = accept_async(stream).await {
+                        serve(ws, protocol, script, requests, requested, refused).await;
+                    }
+                });
+            }
+        });
+
+        MockExchange {
+            addr,
+            requests,
+            requested,
+            refused,
+        }
+    }
+
+    /// Refuse the subscriptions of the channel, Deribit only.
+    pub fn refuse(&self, channel: &str, refusal: Refusal) {
+        self.refused
+            .lock()
+            .unwrap()
+            .insert(channel.to_string(), refusal);
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    /// Requests received so far over all connections.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Wait until a request of the kind (e.g. `subscribe` or `unsubscribe`) arrives for the channel.
+    pub async fn wait_for(&self, kind: &str, channel: &str) {
+        let request = (kind.to_string(), channel.to_string());
+        time::timeout(Duration::from_secs(5), async {
+            loop {
+                let notified = self.requested.notified();
+                if self.requests().contains(&request) {
+                    return;
+                }
+                notified.await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("no {:?} request, received {:?}", request, self.requests()));
+    }
+}
+
+async fn serve<S>(
+    ws: tokio_tungstenite::WebSocketStream<S>,
+    protocol: Protocol,
+    script: Vec<Step>,
+    requests: Arc<Mutex<Vec<Request>>>,
+    requested: Arc<Notify>,
+    refused: Arc<Mutex<HashMap<String, Refusal>>>,
+) where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut write, mut read) = ws.split();
+    let mut script = VecDeque::from(script);
+    let mut subscribed = false;
+
+    loop {
+        let step = if subscribed { script.pop_front() } else { None };
+        match step {
+            Some(Step::Send(text)) => {
+                if write.send(Message::Text(text)).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+            Some(Step::Sleep(delay)) => {
+                time::sleep(delay).await;
+                continue;
+            }
+            Some(Step::Disconnect) => {
+                let _ = write.close().await;
+                return;
+            }
+            None => {}
+        }
+
+        let text = match read.next().await {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(_)) => continue,
+            _ => return,
+        };
+        let refused = refused.lock().unwrap().clone();
+        let Some((request, replies, refusal)) = acknowledge(protocol, &text, &refused) else {
+            continue;
+        };
+        for reply in replies {
+            if write.send(Message::Text(reply.to_string())).await.is_err() {
+                return;
+            }
+        }
+        subscribed |= refusal.is_none() && request.0 == "subscribe";
+        requests.lock().unwrap().push(request);
+        requested.notify_waiters();
+        if refusal == Some(Refusal::HangUp) {
+            let _ = write.close().await;
+            return;
+        }
+    }
+}
+
+/// Request of the frame with the replies of the venue and the refusal of it, if any.
+fn acknowledge(
+    protocol: Protocol,
+    text: &str,
+    refused: &HashMap<String, Refusal>,
+) -> Option<(Request, Vec<Value>, Option<Refusal>)> {
+    let frame: Value = serde_json::from_str(text).ok()?;
+    match protocol {
+        Protocol::Bitstamp => {
+            let event = frame["event"].as_str()?;
+            let channel = frame["data"]["channel"].as_str()?.to_string();
+            let kind = event.strip_prefix("bts:")?.to_string();
+            let reply = json!({
+                "event": format!("bts:{}_succeeded", kind.replace("subscribe", "subscription")),
+                "channel": channel,
+                "data": {},
+            });
+            Some(((kind, channel), vec![reply], None))
+        }
+        Protocol::Deribit => {
+            let method = frame["method"].as_str()?;
+            let kind = method.strip_prefix("public/")?.to_string();
+            // requests without channels, e.g. `public/test`, are recorded with an empty channel
+            let channels = frame["params"]["channels"].clone();
+            let channel = channels[0].as_str().unwrap_or_default().to_string();
+            let refusal = refused
+                .get(&channel)
+                .copied()
+                .filter(|_| kind == "subscribe");
+            let reply = match refusal {
+                Some(Refusal::Reject) => json!({
+                    "jsonrpc": "2.0",
+                    "id": frame["id"],
+                    "error": {"code": -32602, "message": "Invalid params"},
+                }),
+                Some(Refusal::HangUp) => return Some(((kind, channel), vec![], refusal)),
+                None => json!({"jsonrpc": "2.0", "id": frame["id"], "result": channels}),
+            };
+            Some(((kind, channel), vec![reply], refusal))
+        }
+    }
+}
@@ -0,0 +1,389 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{MockExchange, Protocol, Refusal, Step};
+use l2_order_book::core::{fixed_point::Price, messages::Side, SharedOrderBook};
+use l2_order_book::error::Error;
+use l2_order_book::providers::{subscribe_to_provider, ProviderEvent};
+use l2_order_book::utils::config::{
+    Config, ExchangeConfig, Feed, Provider, ProviderConfig, ReconnectConfig,
+};
+use tokio::time;
+
+fn config(provider: Provider, instrument: &str, feed: Feed, url: String) -> Config {
+    Config {
+        exchange: ExchangeConfig {
+            depth_limit: Some(10),
+            instrument: Some(instrument.to_string()),
+            feed: Some(feed),
+            url: Some(url),
+            ..Default::default()
+        },
+        provider: ProviderConfig {
+            name: Some(provider),
+        },
+        reconnect: ReconnectConfig {
+            initial_delay_ms: Some(10),
+            jitter: Some(0.0),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn bitstamp_book(channel: &str, bid: &str, ask: &str) -> Step {
+    Step::Send(format!(
+        r#"{{"data":{{"timestamp":"1721390000","microtimestamp":"1721390000000000","bids":[["{}","1"]],"asks":[["{}","2"]]}},"channel":"{}","event":"data"}}"#,
+        bid, ask, channel
+    ))
+}
+
+fn deribit_book(change_id: u64, prev_change_id: Option<u64>, bids: &str, asks: &str) -> Step {
+    let prev_change_id = prev_change_id
+        .map(|id| format!(r#""prev_change_id":{},"#, id))
+        .unwrap_or_default();
+    Step::Send(format!(
+        r#"{{"jsonrpc":"2.0","method":"subscription","params":{{"channel":"book.BTC-PERPETUAL.100ms","data":{{"type":"change","timestamp":1554373962454,{}"instrument_name":"BTC-PERPETUAL","change_id":{},"bids":{},"asks":{}}}}}}}"#,
+        prev_change_id, change_id, bids, asks
+    ))
+}
+
+/// Wait until the best bid of the book becomes the price.
+async fn wait_for_bid(order_book: &SharedOrderBook, price: f64) {
+    let mut subscription = order_book.subscribe(None);
+    time::timeout(Duration::from_secs(5), async {
+        while order_book.get_best_bid().await != Some(Price::from_f64(price)) {
+            subscription.next().await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("best bid did not become {}", price));
+}
+
+#[tokio::test]
+async fn test_bitstamp_subscribe_update_unsubscribe() {
+    let channel = "order_book_btcusd";
+    let exchange = MockExchange::start(
+        Protocol::Bitstamp,
+        vec![vec![
+            bitstamp_book(channel, "64000", "64010"),
+            Step::Send("not json".to_string()),
+            bitstamp_book(channel, "64001", "64009"),
+        ]],
+    )
+    .await;
+
+    let order_book = SharedOrderBook::initialise(10);
+    let config = config(
+        Provider::Bitstamp,
+        "BTC-USD",
+        Feed::Snapshot,
+        exchange.url(),
+    );
+    let handle = subscribe_to_provider(config, order_book.clone()).unwrap();
+
+    exchange.wait_for("subscribe", channel).await;
+    // the malformed frame is skipped without dropping the connection
+    wait_for_bid(&order_book, 64001.0).await;
+    assert_eq!(
+        order_book.get_best_ask().await,
+        Some(Price::from_f64(64009.0))
+    );
+
+    handle.stop().await.unwrap();
+    exchange.wait_for("unsubscribe", channel).await;
+}
+
+#[tokio::test]
+async fn test_deribit_incremental_feed() {
+    let channel = "book.BTC-PERPETUAL.100ms";
+    let exchange = MockExchange::start(
+        Protocol::Deribit,
+        vec![vec![
+            deribit_book(
+                1,
+                None,
+                r#"[["new",64000.0,10.0],["new",63999.0,20.0]]"#,
+                r#"[["new",64010.0,30.0]]"#,
+            ),
+            deribit_book(2, Some(1), r#"[["delete",64000.0,0.0]]"#, "[]"),
+        ]],
+    )
+    .await;
+
+    let order_book = SharedOrderBook::initialise(10);
+    let config = config(
+        Provider::Deribit,
+        "BTC-PERPETUAL",
+        Feed::Incremental,
+        exchange.url(),
+    );
+    let handle = subscribe_to_provider(config, order_book.clone()).unwrap();
+
+    exchange.wait_for("subscribe", channel).await;
+    wait_for_bid(&order_book, 63999.0).await;
+    assert_eq!(order_book.get_sequence().await, Some(2));
+
+    handle.stop().await.unwrap();
+    exchange.wait_for("unsubscribe", channel).await;
+}
+
+#[tokio::test]
+async fn test_reconnect_after_disconnect() {
+    let channel = "order_book_btcusd";
+    let exchange = MockExchange::start(
+        Protocol::Bitstamp,
+        vec![
+            vec![
+                bitstamp_book(channel, "64000", "64010"),
+                Step::Sleep(Duration::from_millis(20)),
+                Step::Disconnect,
+            ],
+            vec![bitstamp_book(channel, "65000", "65010")],
+        ],
+    )
+    .await;
+
+    let order_book = SharedOrderBook::initialise(10);
+    let config = config(
+        Provider::Bitstamp,
+        "BTC-USD",
+        Feed::Snapshot,
+        exchange.url(),
+    );
+    let handle = subscribe_to_provider(config, order_book.clone()).unwrap();
+    let mut events = handle.events();
+
+    wait_for_bid(&order_book, 65000.0).await;
+    let mut received = vec![];
+    while let Ok(event) = events.try_recv() {
+        received.push(event);
+    }
+    assert!(received
+        .iter()
+        .any(|event| matches!(event, ProviderEvent::Disconnected { .. })));
+    assert!(received
+        .iter()
+        .any(|event| matches!(event, ProviderEvent::Reconnecting { attempt: 1, .. })));
+    assert_eq!(
+        exchange
+            .requests()
+            .iter()
+            .filter(|(kind, _)| kind == "subscribe")
+            .count(),
+        2
+    );
+    let health = handle.health().status();
+    assert!(health.connected);
+    assert_eq!(health.reconnects, 1);
+    assert!(health.last_message_age_ms.is_some());
+
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_deribit_heartbeat_test_request() {
+    let channel = "book.BTC-PERPETUAL.100ms";
+    let exchange = MockExchange::start(
+        Protocol::Deribit,
+        vec![vec![Step::Send(
+            r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"test_request"}}"#
+                .to_string(),
+        )]],
+    )
+    .await;
+
+    let order_book = SharedOrderBook::initialise(10);
+    let config = config(
+        Provider::Deribit,
+        "BTC-PERPETUAL",
+        Feed::Incremental,
+        exchange.url(),
+    );
+    let handle = subscribe_to_provider(config, order_book.clone()).unwrap();
+
+    exchange.wait_for("set_heartbeat", "").await;
+    exchange.wait_for("subscribe", channel).await;
+    exchange.wait_for("test", "").await;
+
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_deribit_rejected_subscription() {
+    let channel = "book.BTC-PERPETUAL.100ms";
+    let exchange = MockExchange::start(Protocol::Deribit, vec![]).await;
+    exchange.refuse(channel, Refusal::Reject);
+
+    let order_book = SharedOrderBook::initialise(10);
+    let mut config = config(
+        Provider::Deribit,
+        "BTC-PERPETUAL",
+        Feed::Incremental,
+        exchange.url(),
+    );
+    config.reconnect.max_attempts = Some(1);
+    let handle = subscribe_to_provider(config, order_book).unwrap();
+
+    let error = time::timeout(Duration::from_secs(5), handle.stopped())
+        .await
+        .expect("provider did not give up")
+        .unwrap_err();
+    let Error::GaveUp { source, .. } = error.as_ref() else {
+        panic!("expected to give up, got {:?}", error);
+    };
+    let Error::Subscribe { instrument, source } = source.as_ref() else {
+        panic!("expected a subscription error, got {:?}", source);
+    };
+    assert_eq!(instrument, "BTC-PERPETUAL");
+    assert_eq!(
+        source.to_string(),
+        "public/subscribe failed with -32602: Invalid params"
+    );
+}
+
+#[tokio::test]
+async fn test_deribit_subscription_fails_when_disconnected() {
+    let channel = "book.BTC-PERPETUAL.100ms";
+    let exchange = MockExchange::start(Protocol::Deribit, vec![]).await;
+    exchange.refuse(channel, Refusal::HangUp);
+
+    let order_book = SharedOrderBook::initialise(10);
+    let mut config = config(
+        Provider::Deribit,
+        "BTC-PERPETUAL",
+        Feed::Incremental,
+        exchange.url(),
+    );
+    config.reconnect.max_attempts = Some(1);
+    let handle = subscribe_to_provider(config, order_book).unwrap();
+
+    // far below the response timeout, the closed connection fails the request at once
+    let error = time::timeout(Duration::from_secs(2), handle.stopped())
+        .await
+        .expect("provider did not give up")
+        .unwrap_err();
+    let Error::GaveUp { source, .. } = error.as_ref() else {
+        panic!("expected to give up, got {:?}", error);
+    };
+    assert_eq!(
+        source.to_string(),
+        "Unable to subscribe to BTC-PERPETUAL: Connection closed before the response to public/subscribe"
+    );
+}
+
+#[tokio::test]
+async fn test_reconnect_when_stale() {
+    let channel = "order_book_btcusd";
+    let exchange = MockExchange::start(
+        Protocol::Bitstamp,
+        vec![vec![bitstamp_book(channel, "64000", "64010")]],
+    )
+    .await;
+
+    let order_book = SharedOrderBook::initialise(10);
+    let mut config = config(
+        Provider::Bitstamp,
+        "BTC-USD",
+        Feed::Snapshot,
+        exchange.url(),
+    );
+    config.exchange.stale_after_ms = Some(100);
+    let handle = subscribe_to_provider(config, order_book.clone()).unwrap();
+    let mut events = handle.events();
+
+    wait_for_bid(&order_book, 64000.0).await;
+    let stale = time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Ok(ProviderEvent::Stale { instrument, .. }) = events.recv().await {
+                return instrument;
+            }
+        }
+    })
+    .await
+    .expect("no stale event");
+    assert_eq!(stale, "BTC-USD");
+    assert!(order_book.is_stale());
+
+    // the silent connection is replaced
+    time::timeout(Duration::from_secs(5), async {
+        while exchange
+            .requests()
+            .iter()
+            .filter(|(kind, _)| kind == "subscribe")
+            .count()
+            < 2
+        {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("no second subscription");
+
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_stopped_after_reconnect_attempts() {
+    // nothing listens on the port once the listener is dropped
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let order_book = SharedOrderBook::initialise(10);
+    let mut config = config(Provider::Bitstamp, "BTC-USD", Feed::Snapshot, url);
+    config.reconnect.max_attempts = Some(2);
+    let handle = subscribe_to_provider(config, order_book).unwrap();
+
+    let error = time::timeout(Duration::from_secs(5), handle.stopped())
+        .await
+        .expect("provider did not give up")
+        .unwrap_err();
+    let Error::GaveUp { attempts, source } = error.as_ref() else {
+        panic!("expected to give up, got {:?}", error);
+    };
+    assert_eq!(*attempts, 2);
+    assert!(matches!(source.as_ref(), Error::Connect { .. }));
+    assert!(!handle.health().status().connected);
+    assert!(matches!(handle.stop().await, Err(Error::Stopped)));
+}
+
+#[tokio::test]
+async fn test_bitstamp_trades() {
+    let exchange = MockExchange::start(
+        Protocol::Bitstamp,
+        vec![vec![
+            bitstamp_book("order_book_btcusd", "64000", "64010"),
+            Step::Send(
+                r#"{"data":{"id":1001,"amount_str":"0.5","price_str":"64010","type":0,"microtimestamp":"1721390000000001"},"channel":"live_trades_btcusd","event":"trade"}"#
+                    .to_string(),
+            ),
+        ]],
+    )
+    .await;
+
+    let order_book = SharedOrderBook::initialise(10);
+    let mut trades = order_book.trades().subscribe();
+    let mut config = config(
+        Provider::Bitstamp,
+        "BTC-USD",
+        Feed::Snapshot,
+        exchange.url(),
+    );
+    config.exchange.trades = Some(true);
+    let handle = subscribe_to_provider(config, order_book.clone()).unwrap();
+
+    exchange.wait_for("subscribe", "live_trades_btcusd").await;
+    let trade = time::timeout(Duration::from_secs(5), trades.recv())
+        .await
+        .expect("no trade")
+        .unwrap();
+    assert_eq!(trade.trade_id, "1001");
+    assert_eq!(trade.aggressor, Side::Buy);
+    assert_eq!(trade.price, Price::from_f64(64010.0));
+    assert_eq!(order_book.trades().recent(10), vec![trade]);
+
+    handle.stop().await.unwrap();
+    exchange.wait_for("unsubscribe", "order_book_btcusd").await;
+}
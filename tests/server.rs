@@ -0,0 +1,266 @@
+use std::{net::SocketAddr, time::Duration};
+
+use futures::{SinkExt, StreamExt};
+use l2_order_book::core::{
+    fixed_point::{Price, Quantity},
+    messages::{OrderBookUpdate, Side},
+    SharedOrderBook,
+};
+use l2_order_book::providers::FeedHealth;
+use l2_order_book::server::{BookDirectory, HttpServer, MetricsServer, WsServer};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time,
+};
+use tokio_tungstenite::{
+    connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream,
+};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+fn update(side: Side, price: f64, quantity: f64) -> OrderBookUpdate {
+    OrderBookUpdate {
+        price: Price::from_f64(price),
+        quantity: Quantity::from_f64(quantity),
+        side,
+    }
+}
+
+async fn send(client: &mut Client, request: Value) {
+    client
+        .send(Message::Text(request.to_string()))
+        .await
+        .unwrap();
+}
+
+/// Next text message of the server as JSON.
+async fn receive(client: &mut Client) -> Value {
+    time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Message::Text(text) = client.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    })
+    .await
+    .expect("no message from the server")
+}
+
+async fn start(order_book: &SharedOrderBook) -> (WsServer, Client) {
+    let books = BookDirectory::new();
+    books.insert("BTC-USD", order_book.clone());
+    let server = WsServer::bind("127.0.0.1:0", books).await.unwrap();
+    let (client, _) = connect_async(format!("ws://{}", server.local_addr()))
+        .await
+        .unwrap();
+    (server, client)
+}
+
+#[tokio::test]
+async fn test_snapshot_then_deltas() {
+    let order_book = SharedOrderBook::initialise(10);
+    order_book
+        .process_snapshot(
+            vec![update(Side::Buy, 100.0, 1.0), update(Side::Buy, 99.0, 2.0)],
+            vec![update(Side::Sell, 101.0, 3.0)],
+        )
+        .await;
+    let (server, mut client) = start(&order_book).await;
+
+    send(
+        &mut client,
+        json!({"op": "subscribe", "instrument": "btcusd", "depth": 1}),
+    )
+    .await;
+    assert_eq!(
+        receive(&mut client).await,
+        json!({"type": "subscribed", "instrument": "btcusd"})
+    );
+    assert_eq!(
+        receive(&mut client).await,
+        json!({
+            "type": "snapshot",
+            "instrument": "btcusd",
+            "sequence": null,
+            "bids": [{"price": "100", "quantity": "1"}],
+            "asks": [{"price": "101", "quantity": "3"}],
+        })
+    );
+
+    // a new best bid pushes the previous one out of the depth
+    order_book
+        .process_update(update(Side::Buy, 100.5, 4.0))
+        .await;
+    assert_eq!(
+        receive(&mut client).await,
+        json!({
+            "type": "delta",
+            "instrument": "btcusd",
+            "sequence": null,
+            "bids": [{"price": "100", "quantity": "0"}, {"price": "100.5", "quantity": "4"}],
+            "asks": [],
+        })
+    );
+
+    send(
+        &mut client,
+        json!({"op": "unsubscribe", "instrument": "btcusd"}),
+    )
+    .await;
+    assert_eq!(
+        receive(&mut client).await,
+        json!({"type": "unsubscribed", "instrument": "btcusd"})
+    );
+    server.stop();
+}
+
+#[tokio::test]
+async fn test_invalid_requests() {
+    let order_book = SharedOrderBook::initialise(10);
+    let (server, mut client) = start(&order_book).await;
+
+    send(
+        &mut client,
+        json!({"op": "subscribe", "instrument": "ETH-USD"}),
+    )
+    .await;
+    assert_eq!(
+        receive(&mut client).await,
+        json!({"type": "error", "message": "Unknown instrument: ETH-USD"})
+    );
+
+    send(
+        &mut client,
+        json!({"op": "unsubscribe", "instrument": "BTC-USD"}),
+    )
+    .await;
+    assert_eq!(
+        receive(&mut client).await,
+        json!({"type": "error", "message": "Not subscribed: BTC-USD"})
+    );
+    server.stop();
+}
+
+/// Status code and body of a GET request.
+async fn http_get_text(addr: SocketAddr, path: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let code = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (code, body.to_string())
+}
+
+/// Status code and JSON body of a GET request.
+async fn http_get(server: &HttpServer, path: &str) -> (u16, Value) {
+    let (code, body) = http_get_text(server.local_addr(), path).await;
+    (code, serde_json::from_str(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_http_book_and_top() {
+    let order_book = SharedOrderBook::initialise(10);
+    order_book
+        .process_snapshot(
+            vec![update(Side::Buy, 100.0, 1.0), update(Side::Buy, 99.0, 2.0)],
+            vec![update(Side::Sell, 101.0, 3.0)],
+        )
+        .await;
+    let books = BookDirectory::new();
+    books.insert("BTC-USD", order_book);
+    let server = HttpServer::bind("127.0.0.1:0", books, vec![])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        http_get(&server, "/books/BTC-USD?depth=1").await,
+        (
+            200,
+            json!({
+                "instrument": "BTC-USD",
+                "sequence": null,
+                "bids": [{"price": "100", "quantity": "1"}],
+                "asks": [{"price": "101", "quantity": "3"}],
+            })
+        )
+    );
+    assert_eq!(
+        http_get(&server, "/books/btcusd/top").await,
+        (
+            200,
+            json!({
+                "instrument": "btcusd",
+                "best_bid": "100",
+                "best_ask": "101",
+                "spread": "1",
+                "mid": "100.5",
+                "stale": false,
+            })
+        )
+    );
+    assert_eq!(
+        http_get(&server, "/books/ETH-USD/top").await,
+        (404, json!({"error": "Unknown instrument: ETH-USD"}))
+    );
+    server.stop();
+}
+
+#[tokio::test]
+async fn test_http_health() {
+    let server = HttpServer::bind(
+        "127.0.0.1:0",
+        BookDirectory::new(),
+        vec![("bitstamp".to_string(), FeedHealth::default())],
+    )
+    .await
+    .unwrap();
+
+    // a provider which never connected
+    assert_eq!(
+        http_get(&server, "/health").await,
+        (
+            503,
+            json!({
+                "status": "degraded",
+                "providers": {
+                    "bitstamp": {"connected": false, "last_message_age_ms": null, "reconnects": 0},
+                },
+            })
+        )
+    );
+    server.stop();
+}
+
+#[tokio::test]
+async fn test_metrics_of_books() {
+    let order_book = SharedOrderBook::initialise(10);
+    order_book
+        .process_snapshot(
+            vec![update(Side::Buy, 100.0, 1.0), update(Side::Buy, 99.0, 2.0)],
+            vec![update(Side::Sell, 101.0, 3.0)],
+        )
+        .await;
+    let books = BookDirectory::new();
+    books.insert("BTC-USD", order_book);
+    let server = MetricsServer::bind("127.0.0.1:0", books).await.unwrap();
+
+    let (code, body) = http_get_text(server.local_addr(), "/metrics").await;
+    assert_eq!(code, 200);
+    for line in [
+        r#"l2_book_depth{instrument="BTC-USD",side="bid"} 2"#,
+        r#"l2_book_depth{instrument="BTC-USD",side="ask"} 1"#,
+        r#"l2_book_spread{instrument="BTC-USD"} 1"#,
+        r#"l2_book_update_age_seconds{instrument="BTC-USD"}"#,
+    ] {
+        assert!(body.contains(line), "missing {} in\n{}", line, body);
+    }
+    server.stop();
+}